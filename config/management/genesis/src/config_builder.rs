@@ -356,9 +356,19 @@ impl BuildSwarm for FullnodeBuilder {
 }
 
 pub fn test_config() -> (NodeConfig, Ed25519PrivateKey) {
+    test_config_with_num_validators(1)
+}
+
+/// Same as `test_config`, but with `num_validators` validators in the genesis validator set. The
+/// returned config is the one of the first validator.
+pub fn test_config_with_num_validators(num_validators: usize) -> (NodeConfig, Ed25519PrivateKey) {
     let path = TempPath::new();
     path.create_as_dir().unwrap();
-    let builder = ValidatorBuilder::new(1, NodeConfig::default_for_validator(), path.path());
+    let builder = ValidatorBuilder::new(
+        num_validators,
+        NodeConfig::default_for_validator(),
+        path.path(),
+    );
     let (mut configs, key) = builder.build_swarm().unwrap();
 
     let mut config = configs.swap_remove(0);
@@ -20,4 +20,4 @@ mod storage_helper;
 pub mod swarm_config;
 
 #[cfg(any(test, feature = "testing"))]
-pub use crate::config_builder::test_config;
+pub use crate::config_builder::{test_config, test_config_with_num_validators};
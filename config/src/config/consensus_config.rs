@@ -20,6 +20,9 @@ pub struct ConsensusConfig {
     pub sync_only: bool,
     // how many times to wait for txns from mempool when propose
     pub mempool_poll_count: u64,
+    // Check every commit against storage once it's persisted. Mismatches are logged and counted,
+    // but don't fail the commit.
+    pub verify_commits: bool,
 }
 
 impl Default for ConsensusConfig {
@@ -36,6 +39,7 @@ impl Default for ConsensusConfig {
             safety_rules: SafetyRulesConfig::default(),
             sync_only: false,
             mempool_poll_count: 1,
+            verify_commits: false,
         }
     }
 }
@@ -68,6 +68,7 @@ fn build_inserter(
     let state_computer = Arc::new(ExecutionProxy::new(
         lec_client,
         StateSynchronizerClient::new(coordinator_sender),
+        None, /* commit_verifier */
     ));
 
     TreeInserter::new_with_store(
@@ -7,7 +7,7 @@ use crate::{
     network::NetworkTask,
     network_interface::{ConsensusNetworkEvents, ConsensusNetworkSender},
     persistent_liveness_storage::StorageWriteProxy,
-    state_computer::ExecutionProxy,
+    state_computer::{storage_commit_verifier, ExecutionProxy},
     txn_manager::MempoolProxy,
     util::time_service::ClockTimeService,
 };
@@ -39,15 +39,21 @@ pub fn start_consensus(
         .enable_all()
         .build()
         .expect("Failed to create Tokio runtime!");
-    let storage = Arc::new(StorageWriteProxy::new(node_config, diem_db));
+    let storage = Arc::new(StorageWriteProxy::new(node_config, diem_db.clone()));
     let txn_manager = Arc::new(MempoolProxy::new(
         consensus_to_mempool_sender,
         node_config.consensus.mempool_poll_count,
     ));
     let execution_correctness_manager = ExecutionCorrectnessManager::new(node_config);
+    let commit_verifier = if node_config.consensus.verify_commits {
+        Some(storage_commit_verifier(diem_db))
+    } else {
+        None
+    };
     let state_computer = Arc::new(ExecutionProxy::new(
         execution_correctness_manager.client(),
         state_sync_client,
+        commit_verifier,
     ));
     let time_service = Arc::new(ClockTimeService::new(runtime.handle().clone()));
 
@@ -51,6 +51,15 @@ pub static COMMITTED_BLOCKS_COUNT: Lazy<IntCounter> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Count of the commits that failed the post-commit verification since last restart.
+pub static COMMIT_VERIFICATION_FAILURES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "diem_consensus_commit_verification_failures",
+        "Count of the commits that failed the post-commit verification since last restart."
+    )
+    .unwrap()
+});
+
 /// Count of the committed transactions since last restart.
 pub static COMMITTED_TXNS_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!(
@@ -189,6 +198,30 @@ pub static NUM_TXNS_PER_BLOCK: Lazy<Histogram> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Histogram of the size of the execution results of blocks, whether they get committed or not.
+/// "txns" is the number of transactions in the block, "kept_txns" the number of those that
+/// will be committed to the ledger, "discarded_txns" of those that won't, and "retried_txns" of
+/// those cut off by a reconfiguration.
+pub static COMPUTE_RESULT_SIZE: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "diem_consensus_compute_result_size",
+        "Histogram for the size of the execution results of blocks",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+/// Histogram of the time spent in the sub-phases of committing blocks: "commit_blocks" is the
+/// storage commit, "notify_state_sync" the notification of state sync that follows it.
+pub static COMMIT_DURATION_S: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "diem_consensus_commit_duration_s",
+        "Histogram of the time spent in the sub-phases of committing blocks",
+        &["phase"]
+    )
+    .unwrap()
+});
+
 pub static BLOCK_TRACING: Lazy<HistogramVec> = Lazy::new(|| {
     register_histogram_vec!(
         "diem_consensus_block_tracing",
@@ -1,35 +1,75 @@
 // Copyright (c) The Diem Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{error::StateSyncError, state_replication::StateComputer};
-use anyhow::Result;
+use crate::{counters, error::StateSyncError, state_replication::StateComputer};
+use anyhow::{ensure, Result};
 use consensus_types::block::Block;
 use diem_crypto::HashValue;
 use diem_infallible::Mutex;
 use diem_logger::prelude::*;
 use diem_metrics::monitor;
-use diem_types::ledger_info::LedgerInfoWithSignatures;
+use diem_types::{
+    ledger_info::LedgerInfoWithSignatures,
+    transaction::{TransactionStatus, Version},
+};
 use execution_correctness::ExecutionCorrectness;
 use executor_types::{Error as ExecutionError, StateComputeResult};
 use fail::fail_point;
 use state_synchronizer::StateSynchronizerClient;
-use std::boxed::Box;
+use std::{boxed::Box, sync::Arc};
+use storage_interface::DbReader;
+
+/// Checks committed blocks against the finality proof they were committed with, e.g. the state
+/// root against an independently computed one. It runs once the commit is persisted and can't be
+/// undone anymore, so an error is an alarm: it's logged and counted, but doesn't fail the commit.
+pub type CommitVerifier =
+    Box<dyn Fn(&[HashValue], &LedgerInfoWithSignatures) -> Result<()> + Send + Sync>;
+
+/// Returns a verifier checking that storage's latest ledger info is the one just committed, i.e.
+/// that the commit made it to storage as proven.
+pub fn storage_commit_verifier(db: Arc<dyn DbReader>) -> CommitVerifier {
+    Box::new(move |_block_ids, finality_proof| {
+        let committed = finality_proof.ledger_info();
+        let stored = db.get_latest_ledger_info()?;
+        let stored = stored.ledger_info();
+        ensure!(
+            stored.version() == committed.version()
+                && stored.transaction_accumulator_hash()
+                    == committed.transaction_accumulator_hash(),
+            "Storage is at version {} with root hash {}, but version {} with root hash {} was \
+             committed",
+            stored.version(),
+            stored.transaction_accumulator_hash(),
+            committed.version(),
+            committed.transaction_accumulator_hash(),
+        );
+        Ok(())
+    })
+}
 
 /// Basic communication with the Execution module;
 /// implements StateComputer traits.
 pub struct ExecutionProxy {
     execution_correctness_client: Mutex<Box<dyn ExecutionCorrectness + Send + Sync>>,
     synchronizer: StateSynchronizerClient,
+    // Latest version known to be committed, by a commit or by state sync. None until either ran.
+    committed_version: Mutex<Option<Version>>,
+    // Run on every commit once it's persisted, before state sync is notified, if set. Failures
+    // don't fail the commit.
+    commit_verifier: Option<CommitVerifier>,
 }
 
 impl ExecutionProxy {
     pub fn new(
         execution_correctness_client: Box<dyn ExecutionCorrectness + Send + Sync>,
         synchronizer: StateSynchronizerClient,
+        commit_verifier: Option<CommitVerifier>,
     ) -> Self {
         Self {
             execution_correctness_client: Mutex::new(execution_correctness_client),
             synchronizer,
+            committed_version: Mutex::new(None),
+            commit_verifier,
         }
     }
 }
@@ -55,12 +95,46 @@ impl StateComputer for ExecutionProxy {
         );
 
         // TODO: figure out error handling for the prologue txn
-        monitor!(
+        let compute_result = monitor!(
             "execute_block",
             self.execution_correctness_client
                 .lock()
                 .execute_block(block.clone(), parent_block_id)
-        )
+        )?;
+
+        let compute_status = compute_result.compute_status();
+        let num_txns = compute_status.len();
+        let num_kept = compute_result.transaction_info_hashes().len();
+        let num_discarded = compute_status
+            .iter()
+            .filter(|status| matches!(status, TransactionStatus::Discard(_)))
+            .count();
+        // Transactions after a reconfiguration are cut off and left to be retried in a later block.
+        let num_retried = compute_status
+            .iter()
+            .filter(|status| **status == TransactionStatus::Retry)
+            .count();
+        for (kind, count) in &[
+            ("txns", num_txns),
+            ("kept_txns", num_kept),
+            ("discarded_txns", num_discarded),
+            ("retried_txns", num_retried),
+        ] {
+            counters::COMPUTE_RESULT_SIZE
+                .with_label_values(&[*kind])
+                .observe(*count as f64);
+        }
+        debug!(
+            block_id = block.id(),
+            parent_id = block.parent_id(),
+            num_txns = num_txns,
+            num_kept = num_kept,
+            num_discarded = num_discarded,
+            num_retried = num_retried,
+            "Executed block",
+        );
+
+        Ok(compute_result)
     }
 
     /// Send a successful commit. A future is fulfilled when the state is finalized.
@@ -69,18 +143,48 @@ impl StateComputer for ExecutionProxy {
         block_ids: Vec<HashValue>,
         finality_proof: LedgerInfoWithSignatures,
     ) -> Result<(), ExecutionError> {
+        let version = finality_proof.ledger_info().version();
+        let committed_ids = block_ids.clone();
+        // The proof is consumed by the commit, so keep a copy for the verifier.
+        let verification = self
+            .commit_verifier
+            .as_ref()
+            .map(|verifier| (verifier, finality_proof.clone()));
+        let commit_timer = counters::COMMIT_DURATION_S
+            .with_label_values(&["commit_blocks"])
+            .start_timer();
         let (committed_txns, reconfig_events) = monitor!(
             "commit_block",
             self.execution_correctness_client
                 .lock()
                 .commit_blocks(block_ids, finality_proof)?
         );
-        if let Err(e) = monitor!(
+        commit_timer.observe_duration();
+        *self.committed_version.lock() = Some(version);
+        if let Some((verifier, finality_proof)) = verification {
+            if let Err(e) = verifier(&committed_ids, &finality_proof) {
+                counters::COMMIT_VERIFICATION_FAILURES.inc();
+                error!(
+                    error = ?e,
+                    version = version,
+                    "Committed blocks failed verification",
+                );
+            }
+        }
+        // Configure with e.g. "sleep(500)" to simulate slow state finalization. Like a slow
+        // commit_blocks, this holds up the calling thread before state sync gets notified.
+        fail_point!("consensus::commit_delay");
+        let notify_timer = counters::COMMIT_DURATION_S
+            .with_label_values(&["notify_state_sync"])
+            .start_timer();
+        let notify_result = monitor!(
             "notify_state_sync",
             self.synchronizer
                 .commit(committed_txns, reconfig_events)
                 .await
-        ) {
+        );
+        notify_timer.observe_duration();
+        if let Err(e) = notify_result {
             error!(error = ?e, "Failed to notify state synchronizer");
         }
         Ok(())
@@ -91,6 +195,20 @@ impl StateComputer for ExecutionProxy {
         fail_point!("consensus::sync_to", |_| {
             Err(anyhow::anyhow!("Injected error in sync_to").into())
         });
+        // Nothing to sync if the target was already reached, in which case the executor's cache
+        // is up to date as well and doesn't need to be reset either.
+        let target_version = target.ledger_info().version();
+        let committed_version = *self.committed_version.lock();
+        if let Some(version) = committed_version {
+            if target_version <= version {
+                debug!(
+                    target_version = target_version,
+                    committed_version = version,
+                    "Skipping sync_to an already reached target",
+                );
+                return Ok(());
+            }
+        }
         // Here to start to do state synchronization where ChunkExecutor inside will
         // process chunks and commit to Storage. However, after block execution and
         // commitments, the the sync state of ChunkExecutor may be not up to date so
@@ -101,6 +219,125 @@ impl StateComputer for ExecutionProxy {
         // of BlockExecutor to guarantee the latest committed state is up to date.
         self.execution_correctness_client.lock().reset()?;
         res?;
+        *self.committed_version.lock() = Some(target_version);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diem_types::{
+        block_info::BlockInfo, contract_event::ContractEvent, ledger_info::LedgerInfo,
+        transaction::Transaction,
+    };
+    use futures::{channel::mpsc, StreamExt};
+    use state_synchronizer::coordinator::CoordinatorMessage;
+    use std::{
+        collections::BTreeMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    struct MockExecutionCorrectness {
+        num_resets: Arc<AtomicUsize>,
+    }
+
+    impl ExecutionCorrectness for MockExecutionCorrectness {
+        fn committed_block_id(&mut self) -> Result<HashValue, ExecutionError> {
+            Ok(HashValue::zero())
+        }
+
+        fn reset(&mut self) -> Result<(), ExecutionError> {
+            self.num_resets.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn execute_block(
+            &mut self,
+            _block: Block,
+            _parent_block_id: HashValue,
+        ) -> Result<StateComputeResult, ExecutionError> {
+            unimplemented!()
+        }
+
+        fn commit_blocks(
+            &mut self,
+            _block_ids: Vec<HashValue>,
+            _ledger_info_with_sigs: LedgerInfoWithSignatures,
+        ) -> Result<(Vec<Transaction>, Vec<ContractEvent>), ExecutionError> {
+            Ok((vec![], vec![]))
+        }
+    }
+
+    fn ledger_info_at(version: Version) -> LedgerInfoWithSignatures {
+        LedgerInfoWithSignatures::new(
+            LedgerInfo::new(
+                BlockInfo::new(1, 0, HashValue::zero(), HashValue::zero(), version, 0, None),
+                HashValue::zero(),
+            ),
+            BTreeMap::new(),
+        )
+    }
+
+    fn new_proxy(
+        num_resets: Arc<AtomicUsize>,
+        commit_verifier: Option<CommitVerifier>,
+    ) -> (ExecutionProxy, mpsc::UnboundedReceiver<CoordinatorMessage>) {
+        let (coordinator_sender, coordinator_receiver) = mpsc::unbounded();
+        let proxy = ExecutionProxy::new(
+            Box::new(MockExecutionCorrectness { num_resets }),
+            StateSynchronizerClient::new(coordinator_sender),
+            commit_verifier,
+        );
+        (proxy, coordinator_receiver)
+    }
+
+    #[tokio::test]
+    async fn test_sync_to_reached_target() {
+        let num_resets = Arc::new(AtomicUsize::new(0));
+        let (proxy, mut coordinator_receiver) = new_proxy(num_resets.clone(), None);
+
+        // Let state sync reach the first target.
+        let respond = async {
+            match coordinator_receiver.next().await {
+                Some(CoordinatorMessage::Request(request)) => {
+                    request.callback.send(Ok(())).unwrap();
+                }
+                _ => panic!("Expected a sync request."),
+            }
+        };
+        let (result, ()) = futures::join!(proxy.sync_to(ledger_info_at(10)), respond);
+        result.unwrap();
+        assert_eq!(num_resets.load(Ordering::SeqCst), 1);
+
+        // Targets that were already reached neither go to state sync nor reset the executor.
+        proxy.sync_to(ledger_info_at(10)).await.unwrap();
+        proxy.sync_to(ledger_info_at(5)).await.unwrap();
+        assert_eq!(num_resets.load(Ordering::SeqCst), 1);
+        assert!(coordinator_receiver.try_next().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_commit_verification_failure() {
+        let verifier: CommitVerifier =
+            Box::new(|_, _| Err(anyhow::anyhow!("Injected verification failure")));
+        let (proxy, mut coordinator_receiver) =
+            new_proxy(Arc::new(AtomicUsize::new(0)), Some(verifier));
+        let failures = counters::COMMIT_VERIFICATION_FAILURES.get();
+
+        // The commit is persisted already, so a failed verification doesn't fail it, and state
+        // sync still gets notified.
+        let respond = async {
+            match coordinator_receiver.next().await {
+                Some(CoordinatorMessage::Commit(..)) => (),
+                _ => panic!("Expected a commit notification."),
+            }
+        };
+        let (result, ()) = futures::join!(
+            proxy.commit(vec![HashValue::random()], ledger_info_at(10)),
+            respond
+        );
+        result.unwrap();
+        assert_eq!(counters::COMMIT_VERIFICATION_FAILURES.get(), failures + 1);
+    }
+}
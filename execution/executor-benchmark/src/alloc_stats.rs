@@ -0,0 +1,105 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NUM_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static BYTES_FREED: AtomicUsize = AtomicUsize::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting what is allocated and freed through it. It only counts once
+/// it's installed as the `#[global_allocator]`, which the benchmark binary does when built with
+/// the `alloc-stats` feature.
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    fn record_alloc(size: usize) {
+        NUM_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES_ALLOCATED.fetch_add(size, Ordering::Relaxed);
+        let live_bytes = LIVE_BYTES.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK_LIVE_BYTES.fetch_max(live_bytes, Ordering::Relaxed);
+    }
+
+    fn record_free(size: usize) {
+        BYTES_FREED.fetch_add(size, Ordering::Relaxed);
+        LIVE_BYTES.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            Self::record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            Self::record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        Self::record_free(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            Self::record_free(layout.size());
+            Self::record_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Allocations made since `AllocationStats::start`. The counters are process-wide: they include
+/// the allocations of all threads, so measurements overlapping in time mix up each other's
+/// allocations and reset each other's peak.
+#[derive(Clone, Copy, Debug)]
+pub struct AllocationStats {
+    pub num_allocations: usize,
+    pub bytes_allocated: usize,
+    pub bytes_freed: usize,
+    /// Largest number of bytes that were allocated and not yet freed at any one time, including
+    /// those that were live at the start.
+    pub peak_live_bytes: usize,
+}
+
+impl AllocationStats {
+    /// Takes the counts to measure from and restarts tracking the peak from the bytes live now.
+    pub fn start() -> Self {
+        PEAK_LIVE_BYTES.store(LIVE_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+        Self::current()
+    }
+
+    /// Allocations made since `self` was taken with `start`.
+    pub fn since(&self) -> Self {
+        let current = Self::current();
+        Self {
+            num_allocations: current.num_allocations - self.num_allocations,
+            bytes_allocated: current.bytes_allocated - self.bytes_allocated,
+            bytes_freed: current.bytes_freed - self.bytes_freed,
+            peak_live_bytes: current.peak_live_bytes,
+        }
+    }
+
+    fn current() -> Self {
+        Self {
+            num_allocations: NUM_ALLOCATIONS.load(Ordering::Relaxed),
+            bytes_allocated: BYTES_ALLOCATED.load(Ordering::Relaxed),
+            bytes_freed: BYTES_FREED.load(Ordering::Relaxed),
+            peak_live_bytes: PEAK_LIVE_BYTES.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -1,28 +1,47 @@
 // Copyright (c) The Diem Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+#[cfg(feature = "alloc-stats")]
+mod alloc_stats;
+mod report;
+mod script;
+
+#[cfg(feature = "alloc-stats")]
+pub use alloc_stats::{AllocationStats, CountingAllocator};
+pub use report::{BenchmarkReport, OutputFormat, PhaseComparison, PhaseReport, ReportComparison};
+pub use script::{ScriptArgument, ScriptTemplate};
+
+use anyhow::{bail, format_err, Context, Result};
 use diem_config::{
     config::{NodeConfig, RocksdbConfig},
     utils::get_genesis_txn,
 };
 use diem_crypto::{
     ed25519::{Ed25519PrivateKey, Ed25519PublicKey},
-    hash::HashValue,
-    PrivateKey, SigningKey, Uniform,
+    hash::{CryptoHash, HashValue, PRE_GENESIS_BLOCK_ID},
+    PrivateKey, SigningKey, Uniform, ValidCryptoMaterialStringExt,
 };
 use diem_logger::prelude::*;
 use diem_types::{
     account_address::AccountAddress,
     account_config::{
-        testnet_dd_account_address, treasury_compliance_account_address, xus_tag, AccountResource,
-        XUS_NAME,
+        diem_root_address, from_currency_code_string, testnet_dd_account_address,
+        treasury_compliance_account_address, type_tag_for_currency_code, xus_tag, AccountResource,
+        BalanceResource, XUS_NAME,
     },
+    account_state::AccountState,
     block_info::BlockInfo,
+    block_metadata::BlockMetadata,
     chain_id::ChainId,
+    contract_event::ContractEvent,
+    event::EventKey,
     ledger_info::{LedgerInfo, LedgerInfoWithSignatures},
+    on_chain_config::{OnChainConfig, VMConfig},
     transaction::{
         authenticator::AuthenticationKey, RawTransaction, Script, SignedTransaction, Transaction,
+        TransactionArgument, TransactionInfo, TransactionPayload, TransactionStatus, Version,
     },
+    vm_status::KeptVMStatus,
 };
 use diem_vm::DiemVM;
 use diemdb::DiemDB;
@@ -31,20 +50,281 @@ use executor::{
     Executor,
 };
 use executor_types::BlockExecutor;
-use rand::{rngs::StdRng, SeedableRng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     convert::TryFrom,
-    path::PathBuf,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
     sync::{mpsc, Arc},
+    time::Duration,
 };
 use storage_client::StorageClient;
 use storage_interface::{DbReader, DbReaderWriter};
 use storage_service::start_storage_service_with_db;
+use structopt::StructOpt;
 use transaction_builder::{
     encode_create_parent_vasp_account_script, encode_peer_to_peer_with_metadata_script,
 };
 
+/// Maximum number of transactions fetched from storage in one query.
+const MAX_TXNS_PER_QUERY: u64 = 1000;
+
+// Optional knobs of `run_benchmark`. Not a doc comment on purpose: structopt would turn it into
+// the `about` text of the binary this is flattened into.
+#[derive(Clone, Debug, StructOpt)]
+pub struct BenchmarkOpt {
+    /// Fold the effects of every committed transaction, in commit order, and the resulting states
+    /// of the accounts every block touches into a digest that is printed at the end of the run.
+    /// Two runs of the same workload must produce the same digest; a mismatch flags
+    /// nondeterministic execution. Can't be combined with more than one generator, whose blocks
+    /// interleave in no particular order, or with a transfer duration, which makes the number of
+    /// blocks vary from run to run.
+    #[structopt(long)]
+    pub write_set_digest: bool,
+
+    /// Fail the run if the digest doesn't match this one. Implies `--write-set-digest`.
+    #[structopt(long)]
+    pub expected_write_set_digest: Option<HashValue>,
+
+    /// Fail the run on the first transaction that is not executed successfully, instead of
+    /// silently counting it towards the throughput. Useful to gate CI on the benchmark.
+    #[structopt(long)]
+    pub strict: bool,
+
+    /// Number of validators in the genesis validator set. More validators make the on-chain
+    /// configs read by every transaction prologue larger. The registered currencies are fixed by
+    /// the Move genesis and can't be configured.
+    #[structopt(long, default_value = "1")]
+    pub num_validators: usize,
+
+    /// Compute transaction expiration times from the latest on-chain timestamp instead of the
+    /// local clock, e.g. when running against a database whose chain time differs from it.
+    #[structopt(long)]
+    pub expiration_from_chain_time: bool,
+
+    /// Number of threads generating the transfers, each over a disjoint range of accounts. Use
+    /// more than one when signing is slower than execution.
+    #[structopt(long, default_value = "1")]
+    pub num_generators: usize,
+
+    /// Fraction, between 0 and 1, of the transfers that are generated already expired. They are
+    /// rejected by the prologue, which measures the cost of its early-abort path against that of
+    /// full execution. Can't be combined with `--strict`.
+    #[structopt(long, default_value = "0")]
+    pub expired_fraction: f64,
+
+    /// Block sizes the transfer phase goes through, e.g. `100,500,1000`, each for
+    /// `num_transfer_blocks` blocks, instead of the single `block_size`. The TPS is reported per
+    /// block size at the end of the run.
+    #[structopt(long, use_delimiter = true)]
+    pub transfer_block_sizes: Vec<usize>,
+
+    /// Resume against the existing database in `db_dir`, which must be at this version, instead
+    /// of starting from genesis: account creation and minting are skipped and only the transfers
+    /// are run. The accounts must have been created by a previous run with at least as many
+    /// accounts. Useful for soak tests spanning several runs.
+    #[structopt(long)]
+    pub replay_from_version: Option<Version>,
+
+    /// Don't check that every account exists once the account creation blocks are committed.
+    /// Without the check, failed creations only show up later as failing mints and transfers.
+    #[structopt(long)]
+    pub skip_account_creation_check: bool,
+
+    /// How the benchmark binary prints the summary of the run at the end: `table` or `none`. The
+    /// library functions only return the report and leave printing it to their caller.
+    #[structopt(long, default_value = "table", possible_values = &["table", "none"])]
+    pub output_format: OutputFormat,
+
+    /// Currency the transfers pay gas in. It must be registered on chain; unless it's XUS, the
+    /// accounts are created holding all currencies. Setup transactions always pay in XUS.
+    #[structopt(long, default_value = "XUS")]
+    pub gas_currency: String,
+
+    /// Pick a different sender for every transfer of a block, so no two transfers of a block
+    /// depend on each other through the sender's sequence number. Needs at least as many accounts
+    /// per generator as there are transfers in a block.
+    #[structopt(long)]
+    pub unique_senders: bool,
+
+    /// Offer the transfers at this many transactions per second, split as evenly as possible
+    /// between the generators, instead of as fast as the executor takes them. It must be at least
+    /// the number of generators. Running below saturation gives meaningful latencies at a known
+    /// load.
+    #[structopt(long)]
+    pub target_tps: Option<u64>,
+
+    /// Start every block with a `BlockMetadata` transaction proposed by the first genesis
+    /// validator, like consensus does. This matches the shape of production blocks and runs the
+    /// block prologue, which makes the run more realistic but slower. Chain time advances by one
+    /// microsecond per block, so runs stay reproducible.
+    #[structopt(long)]
+    pub block_metadata: bool,
+
+    /// Write the generated accounts to this file as CSV, one `address,public_key,auth_key_prefix`
+    /// line per account in hex, so other tools can target them.
+    #[structopt(long, parse(from_os_str))]
+    pub accounts_file: Option<PathBuf>,
+
+    /// Keep generating transfers for this many seconds instead of for `num_transfer_blocks`
+    /// blocks, e.g. for soak testing. With `--transfer-block-sizes`, each block size runs this
+    /// long. The number of blocks that were executed is reported at the end.
+    #[structopt(long)]
+    pub transfer_duration_secs: Option<u64>,
+
+    /// Compiled Move script to run in place of the transfer script, from the funded accounts.
+    /// Balances aren't verified at the end then, since the script may move funds arbitrarily.
+    #[structopt(long, parse(from_os_str))]
+    pub script: Option<PathBuf>,
+
+    /// Arguments of `--script`, e.g. `{receiver},1u64`. `{sender}` and `{receiver}` are replaced
+    /// by the sending account and another random account of every transaction. They must match
+    /// the script's parameters, not counting a leading `&signer`.
+    #[structopt(long, use_delimiter = true)]
+    pub script_args: Vec<String>,
+
+    /// Fail, naming the stuck thread, if the generator and executor threads haven't finished
+    /// this many seconds after they were started, instead of hanging e.g. on a deadlock.
+    #[structopt(long)]
+    pub run_timeout_secs: Option<u64>,
+
+    /// How much every account is minted: `fixed` for `init_account_balance` each, `uniform:MIN:MAX`
+    /// or the heavy-tailed `pareto:MIN:MAX:ALPHA` for amounts drawn per account. Accounts with
+    /// less money run out of it sooner, which makes more transfers abort.
+    #[structopt(long, default_value = "fixed")]
+    pub mint_distribution: MintDistribution,
+
+    /// Pad every transfer with a metadata blob of this many bytes, to measure the per-byte cost
+    /// of transactions. The run fails up front if that pushes the transfers above the maximum
+    /// transaction size of the on-chain gas schedule, which the VM would reject them for.
+    #[structopt(long, default_value = "0")]
+    pub txn_padding_bytes: usize,
+}
+
+impl Default for BenchmarkOpt {
+    fn default() -> Self {
+        Self {
+            write_set_digest: false,
+            expected_write_set_digest: None,
+            strict: false,
+            num_validators: 1,
+            expiration_from_chain_time: false,
+            num_generators: 1,
+            expired_fraction: 0.0,
+            transfer_block_sizes: vec![],
+            replay_from_version: None,
+            skip_account_creation_check: false,
+            output_format: OutputFormat::Table,
+            gas_currency: XUS_NAME.to_string(),
+            unique_senders: false,
+            target_tps: None,
+            block_metadata: false,
+            accounts_file: None,
+            transfer_duration_secs: None,
+            script: None,
+            script_args: vec![],
+            run_timeout_secs: None,
+            mint_distribution: MintDistribution::Fixed,
+            txn_padding_bytes: 0,
+        }
+    }
+}
+
+/// Distribution of the amounts minted to the accounts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MintDistribution {
+    /// Every account gets `init_account_balance`.
+    Fixed,
+    /// Amounts are uniformly distributed between `min` and `max`, both inclusive.
+    Uniform { min: u64, max: u64 },
+    /// Amounts follow a Pareto distribution with shape `alpha` starting at `min`, capped at `max`.
+    /// The smaller `alpha`, the heavier the tail.
+    Pareto { min: u64, max: u64, alpha: f64 },
+}
+
+impl MintDistribution {
+    fn sample(&self, rng: &mut StdRng, init_account_balance: u64) -> u64 {
+        match *self {
+            MintDistribution::Fixed => init_account_balance,
+            MintDistribution::Uniform { min, max } => {
+                rng.sample(rand::distributions::Uniform::new_inclusive(min, max))
+            }
+            MintDistribution::Pareto { min, max, alpha } => {
+                // Inverse transform sampling. `1 - u` is in (0, 1], so this never divides by zero.
+                let u: f64 = rng.gen();
+                let amount = min as f64 / (1.0 - u).powf(1.0 / alpha);
+                if amount >= max as f64 {
+                    max
+                } else {
+                    amount as u64
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for MintDistribution {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let distribution = match parts.as_slice() {
+            ["fixed"] => MintDistribution::Fixed,
+            ["uniform", min, max] => MintDistribution::Uniform {
+                min: min.parse()?,
+                max: max.parse()?,
+            },
+            ["pareto", min, max, alpha] => MintDistribution::Pareto {
+                min: min.parse()?,
+                max: max.parse()?,
+                alpha: alpha.parse()?,
+            },
+            _ => bail!(
+                "Unknown mint distribution {}, expected fixed, uniform:MIN:MAX or \
+                 pareto:MIN:MAX:ALPHA.",
+                s
+            ),
+        };
+        match distribution {
+            MintDistribution::Uniform { min, max } if min > max => {
+                bail!("Minimum {} exceeds maximum {}.", min, max)
+            }
+            MintDistribution::Pareto { min, max, alpha } => {
+                if min == 0 || min > max {
+                    bail!(
+                        "Pareto needs 0 < minimum <= maximum, got {} and {}.",
+                        min,
+                        max
+                    );
+                }
+                if alpha.is_nan() || alpha <= 0.0 {
+                    bail!("Pareto shape must be positive, got {}.", alpha);
+                }
+            }
+            _ => (),
+        }
+        Ok(distribution)
+    }
+}
+
+/// Called with the committed transactions and reconfiguration events of every block, the way
+/// consensus hands them to state sync after a commit.
+pub type CommitCallback = Box<dyn FnMut(Vec<Transaction>, Vec<ContractEvent>) + Send>;
+
+/// A block of generated transactions on its way from the generator to the executor.
+pub struct TransactionBlock {
+    /// Optional tag describing the workload the block belongs to. It is included in the per-block
+    /// output of the executor so results of mixed workloads can be grouped by type.
+    pub label: Option<String>,
+    pub transactions: Vec<Transaction>,
+    /// Accounts created by the block, which the executor can check to exist once it's committed.
+    pub new_accounts: Vec<AccountAddress>,
+}
+
 struct AccountData {
     private_key: Ed25519PrivateKey,
     public_key: Ed25519PublicKey,
@@ -60,30 +340,151 @@ impl AccountData {
     }
 }
 
-struct TransactionGenerator {
+/// Token bucket pacing blocks to a target rate. It is refilled at `target_tps` tokens per second,
+/// and sending a block of `n` transactions waits until `n` tokens are available.
+struct RateLimiter {
+    target_tps: u64,
+    start: std::time::Instant,
+    num_txns: u64,
+}
+
+impl RateLimiter {
+    fn new(target_tps: u64) -> Self {
+        Self {
+            target_tps,
+            start: std::time::Instant::now(),
+            num_txns: 0,
+        }
+    }
+
+    /// Blocks until `num_txns` more transactions can be sent without exceeding the target rate.
+    fn wait_for(&mut self, num_txns: usize) {
+        self.num_txns += num_txns as u64;
+        let due = Duration::from_nanos(
+            (u128::from(self.num_txns) * 1_000_000_000 / u128::from(self.target_tps)) as u64,
+        );
+        let elapsed = self.start.elapsed();
+        if due > elapsed {
+            std::thread::sleep(due - elapsed);
+        }
+    }
+
+    /// Rate the transactions were actually sent at. It falls short of the target if the executor
+    /// can't keep up and the channel pushes back.
+    fn achieved_tps(&self) -> u128 {
+        report::tps(self.num_txns as usize, self.start.elapsed())
+    }
+}
+
+/// A named thread that can be waited for with a timeout, unlike with a plain `JoinHandle`.
+struct WatchedThread<T> {
+    name: String,
+    handle: std::thread::JoinHandle<T>,
+    done_receiver: mpsc::Receiver<()>,
+}
+
+impl<T: Send + 'static> WatchedThread<T> {
+    fn spawn(name: &str, f: impl FnOnce() -> T + Send + 'static) -> Self {
+        let (done_sender, done_receiver) = mpsc::channel();
+        let handle = std::thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let result = f();
+                // The receiver is gone if the run has already given up on this thread.
+                let _ = done_sender.send(());
+                result
+            })
+            .unwrap_or_else(|_| panic!("Failed to spawn {} thread.", name));
+        Self {
+            name: name.to_string(),
+            handle,
+            done_receiver,
+        }
+    }
+
+    /// Waits for the thread to finish, but only until `deadline` if there is one. Returns an error
+    /// naming the thread if it's still running by then, or if it panicked.
+    fn join(self, deadline: Option<std::time::Instant>) -> Result<T> {
+        if let Some(deadline) = deadline {
+            let timeout = deadline.saturating_duration_since(std::time::Instant::now());
+            // A disconnected channel means the thread panicked, which joining reports below.
+            if let Err(mpsc::RecvTimeoutError::Timeout) = self.done_receiver.recv_timeout(timeout) {
+                bail!(
+                    "The {} thread did not finish before the run timed out.",
+                    self.name
+                );
+            }
+        }
+        self.handle
+            .join()
+            .map_err(|_| format_err!("The {} thread panicked.", self.name))
+    }
+}
+
+/// Seed used to derive account keys and drive transfer sampling, for deterministic generation.
+const GENERATOR_SEED: [u8; 32] = [1u8; 32];
+
+/// Seed used to draw the mint amounts. Separate from `GENERATOR_SEED`, so drawing them doesn't
+/// change the transfers that are generated.
+const MINT_SEED: [u8; 32] = [2u8; 32];
+
+pub struct TransactionGenerator {
     /// The current state of the accounts. The main purpose is to keep track of the sequence number
     /// so generated transactions are guaranteed to be successfully executed.
     accounts: Vec<AccountData>,
 
-    /// Used to mint accounts.
-    genesis_key: Ed25519PrivateKey,
+    /// Used to mint accounts. Shared with the generators split off for concurrent transfers.
+    genesis_key: Arc<Ed25519PrivateKey>,
 
     /// For deterministic transaction generation.
     rng: StdRng,
 
+    /// `rng` as it was right after generating the account keys, for `reset` to rewind to.
+    initial_rng: StdRng,
+
+    /// If set, transactions expire relative to this on-chain timestamp (in microseconds) instead
+    /// of the local clock.
+    chain_timestamp_usecs: Option<u64>,
+
+    /// Fraction of the transfers that are generated already expired.
+    expired_fraction: f64,
+
+    /// Currency the transfers pay gas in.
+    gas_currency: String,
+
+    /// Chain the transactions are signed for, which must match the one in the DB.
+    chain_id: ChainId,
+
+    /// Whether every transfer of a block has a different sender.
+    unique_senders: bool,
+
+    /// Rate, in transactions per second, to offer the transfers at, if limited.
+    target_tps: Option<u64>,
+
+    /// How long to keep generating transfers for, if bounded by time rather than block count.
+    transfer_duration: Option<Duration>,
+
+    /// Script the transfers run instead of the peer to peer transfer script, if any.
+    script: Option<Arc<ScriptTemplate>>,
+
+    /// Amount minted to every account, in the order of `accounts`, unless it's the same for all.
+    mint_amounts: Option<Vec<u64>>,
+
+    /// Size of the metadata every transfer is padded with.
+    padding_bytes: usize,
+
     /// Each generated block of transactions are sent to this channel. Using `SyncSender` to make
     /// sure if execution is slow to consume the transactions, we do not run out of memory.
-    block_sender: Option<mpsc::SyncSender<Vec<Transaction>>>,
+    block_sender: Option<mpsc::SyncSender<TransactionBlock>>,
 }
 
 impl TransactionGenerator {
-    fn new(
+    pub fn new(
         genesis_key: Ed25519PrivateKey,
         num_accounts: usize,
-        block_sender: mpsc::SyncSender<Vec<Transaction>>,
+        block_sender: mpsc::SyncSender<TransactionBlock>,
     ) -> Self {
-        let seed = [1u8; 32];
-        let mut rng = StdRng::from_seed(seed);
+        let mut rng = StdRng::from_seed(GENERATOR_SEED);
 
         let mut accounts = Vec::with_capacity(num_accounts);
         for _i in 0..num_accounts {
@@ -101,16 +502,224 @@ impl TransactionGenerator {
 
         Self {
             accounts,
-            genesis_key,
+            genesis_key: Arc::new(genesis_key),
+            initial_rng: rng.clone(),
             rng,
+            chain_timestamp_usecs: None,
+            expired_fraction: 0.0,
+            gas_currency: XUS_NAME.to_string(),
+            chain_id: ChainId::test(),
+            unique_senders: false,
+            target_tps: None,
+            transfer_duration: None,
+            script: None,
+            mint_amounts: None,
+            padding_bytes: 0,
             block_sender: Some(block_sender),
         }
     }
 
-    fn run(&mut self, init_account_balance: u64, block_size: usize, num_transfer_blocks: usize) {
+    /// Same as `new`, but each account starts at its current on-chain sequence number, so the
+    /// workload can be appended to a database that already contains the accounts rather than only
+    /// to a fresh genesis. Accounts that don't exist yet start at zero. Fails if an account can't
+    /// be read from storage.
+    pub fn new_with_db_state(
+        genesis_key: Ed25519PrivateKey,
+        num_accounts: usize,
+        block_sender: mpsc::SyncSender<TransactionBlock>,
+        db: &dyn DbReader,
+    ) -> Result<Self> {
+        let mut generator = Self::new(genesis_key, num_accounts, block_sender);
+        // DbReader has no batched reads, so issue the lookups in parallel instead.
+        generator
+            .accounts
+            .par_iter_mut()
+            .try_for_each(|account| -> Result<()> {
+                if let Some(blob) = db.get_latest_account_state(account.address)? {
+                    let account_resource = AccountResource::try_from(&blob)?;
+                    account.sequence_number = account_resource.sequence_number();
+                }
+                Ok(())
+            })?;
+        Ok(generator)
+    }
+
+    /// Makes generated transactions expire relative to the given on-chain timestamp instead of the
+    /// local clock, which keeps them valid against a database whose chain time differs from it.
+    pub fn use_chain_timestamp(&mut self, timestamp_usecs: u64) {
+        self.chain_timestamp_usecs = Some(timestamp_usecs);
+    }
+
+    /// Generates the given fraction of the transfers already expired. Their senders' sequence
+    /// numbers are not bumped, since the prologue rejects them.
+    pub fn set_expired_fraction(&mut self, expired_fraction: f64) {
+        assert!(
+            (0.0..=1.0).contains(&expired_fraction),
+            "Expired fraction must be between 0 and 1."
+        );
+        self.expired_fraction = expired_fraction;
+    }
+
+    /// Makes the transfers pay gas in the given currency. Unless it's XUS, accounts are created
+    /// holding all currencies so they can pay in it.
+    pub fn set_gas_currency(&mut self, currency_code: &str) {
+        self.gas_currency = currency_code.to_string();
+    }
+
+    /// Signs transactions for `chain_id` instead of the test chain.
+    pub fn set_chain_id(&mut self, chain_id: ChainId) {
+        self.chain_id = chain_id;
+    }
+
+    /// Makes every transfer of a block have a different sender, which requires at least as many
+    /// accounts as transfers in a block.
+    pub fn set_unique_senders(&mut self, unique_senders: bool) {
+        self.unique_senders = unique_senders;
+    }
+
+    /// Limits the rate the transfers are sent at to `target_tps` transactions per second.
+    pub fn set_target_tps(&mut self, target_tps: u64) {
+        assert!(target_tps > 0, "Target TPS must be positive.");
+        self.target_tps = Some(target_tps);
+    }
+
+    /// Generates transfer blocks until `duration` has passed instead of a fixed number of them.
+    /// The block counts passed to `run` and `run_transfers` are ignored then.
+    pub fn set_transfer_duration(&mut self, duration: Duration) {
+        self.transfer_duration = Some(duration);
+    }
+
+    /// Draws the amount minted to every account from `distribution`, instead of minting
+    /// `init_account_balance` to each, and logs the minimum, maximum and mean amount.
+    pub fn set_mint_distribution(
+        &mut self,
+        distribution: MintDistribution,
+        init_account_balance: u64,
+    ) {
+        if distribution == MintDistribution::Fixed {
+            self.mint_amounts = None;
+            return;
+        }
+        let mut rng = StdRng::from_seed(MINT_SEED);
+        let amounts: Vec<u64> = (0..self.accounts.len())
+            .map(|_| distribution.sample(&mut rng, init_account_balance))
+            .collect();
+        let total: u128 = amounts.iter().copied().map(u128::from).sum();
+        info!(
+            "Minting {} in total. min: {}. max: {}. mean: {}.",
+            total,
+            amounts.iter().copied().min().unwrap_or(0),
+            amounts.iter().copied().max().unwrap_or(0),
+            total / std::cmp::max(amounts.len(), 1) as u128,
+        );
+        self.mint_amounts = Some(amounts);
+    }
+
+    /// Sum of the amounts minted to the accounts.
+    pub fn total_mint_amount(&self, init_account_balance: u64) -> u128 {
+        match &self.mint_amounts {
+            Some(amounts) => amounts.iter().copied().map(u128::from).sum(),
+            None => u128::from(init_account_balance) * self.accounts.len() as u128,
+        }
+    }
+
+    /// Pads every transfer with `padding_bytes` of metadata. Custom scripts aren't padded. The VM
+    /// rejects transfers whose `transfer_size` is above its maximum transaction size.
+    pub fn set_padding_bytes(&mut self, padding_bytes: usize) {
+        self.padding_bytes = padding_bytes;
+    }
+
+    /// Runs `script` in the transfer phase instead of the peer to peer transfer script. Its phase
+    /// is labeled `script` rather than `transfer`.
+    pub fn set_script(&mut self, script: ScriptTemplate) {
+        self.script = Some(Arc::new(script));
+    }
+
+    /// Generates the account creations, the mints and then `num_transfer_blocks` blocks of
+    /// transfers, or transfers for the transfer duration if one is set. The transfers are
+    /// generated by `num_generators` threads over disjoint ranges of accounts; each range must
+    /// hold at least two accounts.
+    ///
+    /// If `transfer_block_sizes` is not empty, `num_transfer_blocks` blocks of transfers are
+    /// generated for each of those block sizes in turn, labeled with the block size, instead.
+    pub fn run(
+        &mut self,
+        init_account_balance: u64,
+        block_size: usize,
+        num_transfer_blocks: usize,
+        num_generators: usize,
+        transfer_block_sizes: &[usize],
+    ) {
         self.gen_account_creations(block_size);
         self.gen_mint_transactions(init_account_balance, block_size);
-        self.gen_transfer_transactions(block_size, num_transfer_blocks);
+        self.run_transfers(
+            block_size,
+            num_transfer_blocks,
+            num_generators,
+            transfer_block_sizes,
+        );
+    }
+
+    /// Same as `run`, but only generates the transfers, for accounts that already exist and hold
+    /// a balance, e.g. in a generator created with `new_with_db_state`.
+    pub fn run_transfers(
+        &mut self,
+        block_size: usize,
+        num_transfer_blocks: usize,
+        num_generators: usize,
+        transfer_block_sizes: &[usize],
+    ) {
+        let name = if self.script.is_some() {
+            "script"
+        } else {
+            "transfer"
+        };
+        if transfer_block_sizes.is_empty() {
+            self.gen_transfers(name, block_size, num_transfer_blocks, num_generators);
+        } else {
+            for &transfer_block_size in transfer_block_sizes {
+                self.gen_transfers(
+                    &format!("{}, block size {}", name, transfer_block_size),
+                    transfer_block_size,
+                    num_transfer_blocks,
+                    num_generators,
+                );
+            }
+        }
+    }
+
+    fn gen_transfers(
+        &mut self,
+        label: &str,
+        block_size: usize,
+        num_blocks: usize,
+        num_generators: usize,
+    ) {
+        if num_generators > 1 {
+            self.gen_transfer_transactions_concurrently(
+                label,
+                block_size,
+                num_blocks,
+                num_generators,
+            );
+        } else {
+            self.gen_transfer_transactions(label, block_size, num_blocks);
+        }
+    }
+
+    /// Prepares the generator for another run, reusing the already generated key material: every
+    /// account's sequence number is rewound to zero, the RNG is rewound to where it was after
+    /// generating the keys and new blocks are sent to `block_sender`. The transfers generated
+    /// after a reset are the same as those of a freshly created generator.
+    ///
+    /// The caller must also reset the on-chain state to match (e.g. by starting from a fresh
+    /// genesis DB), otherwise the regenerated transactions will be rejected as stale.
+    pub fn reset(&mut self, block_sender: mpsc::SyncSender<TransactionBlock>) {
+        for account in &mut self.accounts {
+            account.sequence_number = 0;
+        }
+        self.rng = self.initial_rng.clone();
+        self.block_sender = Some(block_sender);
     }
 
     fn gen_account_creations(&self, block_size: usize) {
@@ -130,27 +739,35 @@ impl TransactionGenerator {
                         account.address,
                         account.auth_key_prefix(),
                         vec![],
-                        false, /* add all currencies */
+                        self.gas_currency != XUS_NAME, /* add all currencies */
                     ),
+                    self.expiration_time(),
+                    XUS_NAME,
+                    self.chain_id,
                 );
                 transactions.push(txn);
             }
 
-            self.block_sender
-                .as_ref()
-                .unwrap()
-                .send(transactions)
-                .unwrap();
+            self.send_block(
+                "create_accounts",
+                transactions,
+                block.iter().map(|account| account.address).collect(),
+            );
         }
     }
 
-    /// Generates transactions that allocate `init_account_balance` to every account.
+    /// Generates transactions that allocate `init_account_balance`, or the amount drawn for it, to
+    /// every account.
     fn gen_mint_transactions(&self, init_account_balance: u64, block_size: usize) {
         let testnet_dd_account = testnet_dd_account_address();
 
         for (i, block) in self.accounts.chunks(block_size).enumerate() {
             let mut transactions = Vec::with_capacity(block_size);
             for (j, account) in block.iter().enumerate() {
+                let amount = match &self.mint_amounts {
+                    Some(amounts) => amounts[i * block_size + j],
+                    None => init_account_balance,
+                };
                 let txn = create_transaction(
                     testnet_dd_account,
                     (i * block_size + j) as u64,
@@ -159,61 +776,294 @@ impl TransactionGenerator {
                     encode_peer_to_peer_with_metadata_script(
                         xus_tag(),
                         account.address,
-                        init_account_balance,
+                        amount,
                         vec![],
                         vec![],
                     ),
+                    self.expiration_time(),
+                    XUS_NAME,
+                    self.chain_id,
                 );
                 transactions.push(txn);
             }
 
-            self.block_sender
-                .as_ref()
-                .unwrap()
-                .send(transactions)
-                .unwrap();
+            self.send_block("mint", transactions, vec![]);
         }
     }
 
-    /// Generates transactions for random pairs of accounts.
-    fn gen_transfer_transactions(&mut self, block_size: usize, num_blocks: usize) {
-        for _i in 0..num_blocks {
+    /// Generates transactions for random pairs of accounts, `num_blocks` blocks of them or, if a
+    /// transfer duration is set, as many as fit in it. Returns the number of blocks sent.
+    fn gen_transfer_transactions(
+        &mut self,
+        label: &str,
+        block_size: usize,
+        num_blocks: usize,
+    ) -> usize {
+        let num_accounts = self.accounts.len();
+        let mut rate_limiter = self.target_tps.map(RateLimiter::new);
+        let deadline = self
+            .transfer_duration
+            .map(|duration| std::time::Instant::now() + duration);
+        let mut num_sent = 0;
+        loop {
+            let done = match deadline {
+                Some(deadline) => std::time::Instant::now() >= deadline,
+                None => num_sent == num_blocks,
+            };
+            if done {
+                break;
+            }
             let mut transactions = Vec::with_capacity(block_size);
-            for _j in 0..block_size {
-                let indices = rand::seq::index::sample(&mut self.rng, self.accounts.len(), 2);
-                let sender_idx = indices.index(0);
-                let receiver_idx = indices.index(1);
+            let senders = if self.unique_senders {
+                Some(rand::seq::index::sample(
+                    &mut self.rng,
+                    num_accounts,
+                    block_size,
+                ))
+            } else {
+                None
+            };
+            for j in 0..block_size {
+                let (sender_idx, receiver_idx) = match &senders {
+                    Some(senders) => {
+                        let sender_idx = senders.index(j);
+                        // Any account but the sender.
+                        let offset = self.rng.gen_range(1, num_accounts);
+                        (sender_idx, (sender_idx + offset) % num_accounts)
+                    }
+                    None => {
+                        let indices = rand::seq::index::sample(&mut self.rng, num_accounts, 2);
+                        (indices.index(0), indices.index(1))
+                    }
+                };
+
+                // Chain time never goes backwards from genesis, so an expiration time of zero is
+                // always in the past. Only draw when needed, to keep the default workload intact.
+                let expired =
+                    self.expired_fraction > 0.0 && self.rng.gen_bool(self.expired_fraction);
+                let expiration_time = if expired { 0 } else { self.expiration_time() };
 
                 let sender = &self.accounts[sender_idx];
                 let receiver = &self.accounts[receiver_idx];
+                let script = match &self.script {
+                    Some(script) => script.instantiate(sender.address, receiver.address),
+                    None => self.transfer_script(receiver.address),
+                };
                 let txn = create_transaction(
                     sender.address,
                     sender.sequence_number,
                     &sender.private_key,
                     sender.public_key.clone(),
-                    encode_peer_to_peer_with_metadata_script(
-                        xus_tag(),
-                        receiver.address,
-                        1, /* amount */
-                        vec![],
-                        vec![],
-                    ),
+                    script,
+                    expiration_time,
+                    &self.gas_currency,
+                    self.chain_id,
                 );
                 transactions.push(txn);
 
-                self.accounts[sender_idx].sequence_number += 1;
+                if !expired {
+                    self.accounts[sender_idx].sequence_number += 1;
+                }
             }
 
-            self.block_sender
-                .as_ref()
-                .unwrap()
-                .send(transactions)
-                .unwrap();
+            if let Some(rate_limiter) = rate_limiter.as_mut() {
+                rate_limiter.wait_for(block_size);
+            }
+            self.send_block(label, transactions, vec![]);
+            num_sent += 1;
+        }
+
+        if let Some(rate_limiter) = rate_limiter {
+            info!(
+                "[{}] Offered {} TPS, target {} TPS.",
+                label,
+                rate_limiter.achieved_tps(),
+                rate_limiter.target_tps,
+            );
+        }
+        num_sent
+    }
+
+    /// Generates transfers from `num_generators` threads, each owning a disjoint range of the
+    /// accounts, so that signing doesn't become the bottleneck of the benchmark. Blocks from the
+    /// different threads interleave in the channel, which is fine since they never share a sender.
+    fn gen_transfer_transactions_concurrently(
+        &mut self,
+        label: &str,
+        block_size: usize,
+        num_blocks: usize,
+        num_generators: usize,
+    ) {
+        let start = std::time::Instant::now();
+        let handles: Vec<_> = self
+            .split(num_generators)
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut generator)| {
+                let generator_blocks =
+                    num_blocks / num_generators + usize::from(i < num_blocks % num_generators);
+                let label = label.to_string();
+                std::thread::Builder::new()
+                    .name(format!("txn_generator_{}", i))
+                    .spawn(move || {
+                        let start = std::time::Instant::now();
+                        let num_sent = generator.gen_transfer_transactions(
+                            &label,
+                            block_size,
+                            generator_blocks,
+                        );
+                        let num_txns = num_sent * block_size;
+                        let elapsed = std::time::Instant::now().duration_since(start);
+                        info!(
+                            "Generator {}: generated {} transactions in {} ms. TPS: {}.",
+                            i,
+                            num_txns,
+                            elapsed.as_millis(),
+                            num_txns as u128 * 1_000_000_000 / std::cmp::max(elapsed.as_nanos(), 1),
+                        );
+                        (generator, num_sent)
+                    })
+                    .expect("Failed to spawn transaction generator thread.")
+            })
+            .collect();
+
+        // Take the accounts back in order, so they line up with the ones in `self` again.
+        let mut num_sent = 0;
+        for handle in handles {
+            let (generator, generator_sent) = handle.join().unwrap();
+            self.accounts.extend(generator.accounts);
+            num_sent += generator_sent;
+        }
+
+        let num_txns = num_sent * block_size;
+        let elapsed = std::time::Instant::now().duration_since(start);
+        info!(
+            "{} generators: generated {} transactions in {} ms. TPS: {}.",
+            num_generators,
+            num_txns,
+            elapsed.as_millis(),
+            num_txns as u128 * 1_000_000_000 / std::cmp::max(elapsed.as_nanos(), 1),
+        );
+    }
+
+    /// Moves the accounts into `num_generators` generators over disjoint, contiguous ranges of
+    /// accounts of (almost) equal size. They send to clones of this generator's channel, use
+    /// RNGs seeded from this one and share its target rate, if any, as evenly as possible. The
+    /// target rate must be at least `num_generators`, so every generator gets a share of it.
+    fn split(&mut self, num_generators: usize) -> Vec<TransactionGenerator> {
+        let mut accounts = std::mem::take(&mut self.accounts);
+        let num_accounts = accounts.len();
+        let mut generators = Vec::with_capacity(num_generators);
+        for i in (0..num_generators).rev() {
+            let range_size =
+                num_accounts / num_generators + usize::from(i < num_accounts % num_generators);
+            let range = accounts.split_off(accounts.len() - range_size);
+            let rng = StdRng::from_rng(&mut self.rng).expect("Failed to seed RNG.");
+            generators.push(TransactionGenerator {
+                accounts: range,
+                genesis_key: self.genesis_key.clone(),
+                initial_rng: rng.clone(),
+                rng,
+                chain_timestamp_usecs: self.chain_timestamp_usecs,
+                expired_fraction: self.expired_fraction,
+                gas_currency: self.gas_currency.clone(),
+                chain_id: self.chain_id,
+                unique_senders: self.unique_senders,
+                target_tps: self.target_tps.map(|tps| {
+                    let num_generators = num_generators as u64;
+                    tps / num_generators + u64::from((i as u64) < tps % num_generators)
+                }),
+                transfer_duration: self.transfer_duration,
+                script: self.script.clone(),
+                mint_amounts: None,
+                padding_bytes: self.padding_bytes,
+                block_sender: self.block_sender.clone(),
+            });
         }
+        generators.reverse();
+        generators
+    }
+
+    /// Script of the transfers to `receiver`, padded with `padding_bytes` of metadata.
+    fn transfer_script(&self, receiver: AccountAddress) -> Script {
+        encode_peer_to_peer_with_metadata_script(
+            xus_tag(),
+            receiver,
+            1, /* amount */
+            vec![0; self.padding_bytes],
+            vec![],
+        )
+    }
+
+    /// Size of the raw transfer transactions, padding included. It's the same for all of them,
+    /// since addresses and numbers are serialized at a fixed width.
+    pub fn transfer_size(&self) -> usize {
+        let txn = create_transaction(
+            AccountAddress::ZERO,
+            0, /* sequence_number */
+            &self.genesis_key,
+            self.genesis_key.public_key(),
+            self.transfer_script(AccountAddress::ZERO),
+            self.expiration_time(),
+            &self.gas_currency,
+            self.chain_id,
+        );
+        match txn {
+            Transaction::UserTransaction(txn) => txn.raw_txn_bytes_len(),
+            _ => unreachable!("Transfers are user transactions."),
+        }
+    }
+
+    /// Expiration time, in seconds since the epoch, of a transaction generated now.
+    fn expiration_time(&self) -> u64 {
+        let now = match self.chain_timestamp_usecs {
+            Some(timestamp_usecs) => Duration::from_micros(timestamp_usecs),
+            None => diem_infallible::duration_since_epoch(),
+        };
+        now.as_secs() + 3600
+    }
+
+    fn send_block(
+        &self,
+        label: &str,
+        transactions: Vec<Transaction>,
+        new_accounts: Vec<AccountAddress>,
+    ) {
+        self.block_sender
+            .as_ref()
+            .unwrap()
+            .send(TransactionBlock {
+                label: Some(label.to_string()),
+                transactions,
+                new_accounts,
+            })
+            .unwrap();
+    }
+
+    /// Writes the generated accounts to `path` as CSV with a header line. Addresses, public keys
+    /// and authentication key prefixes are hex encoded.
+    pub fn write_accounts(&self, path: &Path) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "address,public_key,auth_key_prefix")?;
+        for account in &self.accounts {
+            writeln!(
+                file,
+                "{:x},{},{}",
+                account.address,
+                account.public_key.to_encoded_string()?,
+                account
+                    .auth_key_prefix()
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<String>(),
+            )?;
+        }
+        file.flush()?;
+        Ok(())
     }
 
     /// Verifies the sequence numbers in storage match what we have locally.
-    fn verify_sequence_number(&self, db: &dyn DbReader) {
+    pub fn verify_sequence_number(&self, db: &dyn DbReader) {
         for account in &self.accounts {
             let address = account.address;
             let blob = db
@@ -225,8 +1075,181 @@ impl TransactionGenerator {
         }
     }
 
+    /// Verifies the sent and received event streams of the accounts against the events emitted by
+    /// the transactions committed in `first_version..=last_version`: within every stream, the
+    /// sequence numbers must go up by one from event to event, and the last one must be right
+    /// below the count of the stream's handle. Returns an error with the first violation of every
+    /// account that has one.
+    pub fn verify_event_streams(
+        &self,
+        db: &dyn DbReader,
+        first_version: Version,
+        last_version: Version,
+    ) -> Result<()> {
+        // The account every stream belongs to and the count of its handle, by event key.
+        let mut streams = HashMap::new();
+        for account in &self.accounts {
+            let blob = db
+                .get_latest_account_state(account.address)?
+                .ok_or_else(|| format_err!("Account {} must exist.", account.address))?;
+            let account_resource = AccountResource::try_from(&blob)?;
+            for handle in &[
+                account_resource.sent_events(),
+                account_resource.received_events(),
+            ] {
+                streams.insert(*handle.key(), (account.address, handle.count()));
+            }
+        }
+
+        let mut last_sequence_numbers: HashMap<EventKey, u64> = HashMap::new();
+        let mut violations = BTreeMap::new();
+        let mut start_version = first_version;
+        while start_version <= last_version {
+            let limit = std::cmp::min(MAX_TXNS_PER_QUERY, last_version - start_version + 1);
+            let txn_list = db.get_transactions(
+                start_version,
+                limit,
+                last_version,
+                true, /* fetch_events */
+            )?;
+            for (i, events) in txn_list.events.unwrap_or_default().iter().enumerate() {
+                for event in events {
+                    let address = match streams.get(event.key()) {
+                        Some((address, _count)) => address,
+                        None => continue,
+                    };
+                    let sequence_number = event.sequence_number();
+                    if let Some(last) = last_sequence_numbers.insert(*event.key(), sequence_number)
+                    {
+                        if sequence_number != last + 1 {
+                            violations.entry(*address).or_insert_with(|| {
+                                format!(
+                                    "event {} of stream {} at version {} follows event {}",
+                                    sequence_number,
+                                    event.key(),
+                                    start_version + i as u64,
+                                    last,
+                                )
+                            });
+                        }
+                    }
+                }
+            }
+            start_version += limit;
+        }
+
+        for (key, (address, count)) in &streams {
+            if let Some(last) = last_sequence_numbers.get(key) {
+                if last + 1 != *count {
+                    violations.entry(*address).or_insert_with(|| {
+                        format!(
+                            "stream {} ends at event {}, but its handle counts {} events",
+                            key, last, count,
+                        )
+                    });
+                }
+            }
+        }
+
+        if !violations.is_empty() {
+            bail!(
+                "Event streams of {} of {} accounts are out of order: {:?}",
+                violations.len(),
+                self.accounts.len(),
+                violations,
+            );
+        }
+        Ok(())
+    }
+
+    /// Verifies that money was conserved: the XUS balances of the accounts in storage must add up
+    /// to `expected_total`, with no single balance above it (which is what an underflow would look
+    /// like). Gas is free in the benchmark, so there are no fees to account for. The totals are
+    /// logged before checking so a discrepancy can be inspected rather than just failing.
+    pub fn verify_balances(&self, db: &dyn DbReader, expected_total: u128) -> Result<()> {
+        let balances = self.get_balances(db)?;
+        let total_balance: u128 = balances.iter().copied().map(u128::from).sum();
+        let min_balance = balances.iter().copied().min().unwrap_or(0);
+        let max_balance = balances.iter().copied().max().unwrap_or(0);
+
+        info!(
+            "Expected total: {}. total: {}. difference: {}. min balance: {}. max balance: {}.",
+            expected_total,
+            total_balance,
+            expected_total as i128 - total_balance as i128,
+            min_balance,
+            max_balance,
+        );
+        if u128::from(max_balance) > expected_total {
+            bail!(
+                "Balance {} exceeds the expected total {}.",
+                max_balance,
+                expected_total,
+            );
+        }
+        if total_balance != expected_total {
+            bail!(
+                "Balances add up to {}, expected {}.",
+                total_balance,
+                expected_total,
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns an error listing the accounts in storage that can't pay gas in the given currency.
+    pub fn verify_currency(&self, db: &dyn DbReader, currency_code: &str) -> Result<()> {
+        let access_path = BalanceResource::access_path_for(type_tag_for_currency_code(
+            from_currency_code_string(currency_code)?,
+        ));
+        let mut missing = vec![];
+        for account in &self.accounts {
+            let holds_currency = match db.get_latest_account_state(account.address)? {
+                Some(blob) => AccountState::try_from(&blob)?
+                    .get_resource_impl::<BalanceResource>(&access_path)?
+                    .is_some(),
+                None => false,
+            };
+            if !holds_currency {
+                missing.push(account.address);
+            }
+        }
+        if !missing.is_empty() {
+            bail!(
+                "{} of {} accounts don't hold {}: {:?}",
+                missing.len(),
+                self.accounts.len(),
+                currency_code,
+                missing,
+            );
+        }
+        Ok(())
+    }
+
+    /// Sum of the XUS balances of the accounts in storage.
+    pub fn total_balance(&self, db: &dyn DbReader) -> Result<u128> {
+        Ok(self.get_balances(db)?.into_iter().map(u128::from).sum())
+    }
+
+    fn get_balances(&self, db: &dyn DbReader) -> Result<Vec<u64>> {
+        self.accounts
+            .par_iter()
+            .map(|account| -> Result<u64> {
+                let blob = db
+                    .get_latest_account_state(account.address)?
+                    .ok_or_else(|| format_err!("Account {} must exist.", account.address))?;
+                Ok(AccountState::try_from(&blob)?
+                    .get_resource_impl::<BalanceResource>(&BalanceResource::access_path_for(
+                        xus_tag(),
+                    ))?
+                    .ok_or_else(|| format_err!("Account {} must hold XUS.", account.address))?
+                    .coin())
+            })
+            .collect()
+    }
+
     /// Drops the sender to notify the receiving end of the channel.
-    fn drop_sender(&mut self) {
+    pub fn drop_sender(&mut self) {
         self.block_sender.take().unwrap();
     }
 }
@@ -234,48 +1257,135 @@ impl TransactionGenerator {
 struct TransactionExecutor {
     executor: Executor<DiemVM>,
     parent_block_id: HashValue,
-    block_receiver: mpsc::Receiver<Vec<Transaction>>,
+    block_receiver: mpsc::Receiver<TransactionBlock>,
+    db: Arc<dyn DbReader>,
+
+    /// Rolling digest over the committed transactions, if requested.
+    write_set_digest: Option<HashValue>,
+
+    /// Whether to fail on the first transaction that is not executed successfully.
+    strict: bool,
+
+    /// Whether to fail if an account that a block creates doesn't exist after it's committed.
+    check_new_accounts: bool,
+
+    /// Validator to propose the `BlockMetadata` transaction every block starts with, if any.
+    proposer: Option<AccountAddress>,
+
+    /// Invoked after every commit, if set. Its latency is measured separately from the commit's.
+    commit_callback: Option<CommitCallback>,
 }
 
 impl TransactionExecutor {
     fn new(
         executor: Executor<DiemVM>,
         parent_block_id: HashValue,
-        block_receiver: mpsc::Receiver<Vec<Transaction>>,
+        block_receiver: mpsc::Receiver<TransactionBlock>,
+        db: Arc<dyn DbReader>,
+        record_write_set_digest: bool,
+        strict: bool,
+        check_new_accounts: bool,
+        proposer: Option<AccountAddress>,
+        commit_callback: Option<CommitCallback>,
     ) -> Self {
         Self {
             executor,
             parent_block_id,
             block_receiver,
+            db,
+            write_set_digest: if record_write_set_digest {
+                Some(HashValue::zero())
+            } else {
+                None
+            },
+            strict,
+            check_new_accounts,
+            proposer,
+            commit_callback,
         }
     }
 
-    fn run(&mut self) {
-        let mut version = 0;
+    /// Executes and commits blocks until the sending end of the channel is dropped. Returns the
+    /// per-phase results and the final write set digest, if it was requested.
+    ///
+    /// In strict mode, an error is returned as soon as a transaction is not executed successfully.
+    /// Likewise, if checking new accounts, as soon as a block fails to create one of them.
+    fn run(&mut self) -> Result<BenchmarkReport> {
+        // Genesis on a fresh DB, or wherever a previous run left off when replaying.
+        let (mut version, mut timestamp_usecs) = self.db.get_latest_commit_metadata()?;
+        let mut round = 0;
+        let mut total_txns = 0;
+        let mut total_accepted = 0;
+        let mut total_time = Duration::from_secs(0);
+        let mut report = BenchmarkReport::default();
 
-        while let Ok(transactions) = self.block_receiver.recv() {
+        while let Ok(TransactionBlock {
+            label,
+            transactions,
+            new_accounts,
+        }) = self.block_receiver.recv()
+        {
             let num_txns = transactions.len();
-            version += num_txns as u64;
+            let num_bytes: usize = transactions
+                .iter()
+                .map(|txn| match txn {
+                    Transaction::UserTransaction(txn) => txn.raw_txn_bytes_len(),
+                    _ => 0,
+                })
+                .sum();
+            let first_version = version + 1;
+            let block_id = HashValue::random();
+            let touched_accounts = if self.write_set_digest.is_some() {
+                touched_accounts(&transactions)
+            } else {
+                BTreeSet::new()
+            };
+
+            let block = match self.proposer {
+                Some(proposer) => {
+                    round += 1;
+                    timestamp_usecs += 1;
+                    let block_metadata = BlockMetadata::new(
+                        block_id,
+                        round,
+                        timestamp_usecs,
+                        vec![], /* previous_block_votes */
+                        proposer,
+                    );
+                    std::iter::once(Transaction::BlockMetadata(block_metadata))
+                        .chain(transactions)
+                        .collect()
+                }
+                None => transactions,
+            };
 
             let execute_start = std::time::Instant::now();
 
-            let block_id = HashValue::random();
             let output = self
                 .executor
-                .execute_block((block_id, transactions.clone()), self.parent_block_id)
+                .execute_block((block_id, block), self.parent_block_id)
                 .unwrap();
+            // Discarded transactions don't take up a version.
+            version = output.version();
+
+            if self.strict {
+                if let Err(e) = Self::check_all_executed(output.compute_status(), first_version) {
+                    self.drain();
+                    return Err(e);
+                }
+            }
 
             let execute_time = std::time::Instant::now().duration_since(execute_start);
             let commit_start = std::time::Instant::now();
 
             let block_info = BlockInfo::new(
                 1,        /* epoch */
-                0,        /* round, doesn't matter */
+                round,    /* doesn't matter */
                 block_id, /* id, doesn't matter */
                 output.root_hash(),
                 version,
-                0,    /* timestamp_usecs, doesn't matter */
-                None, /* next_epoch_state */
+                timestamp_usecs, /* chain time, only changes with block metadata */
+                None,            /* next_epoch_state */
             );
             let ledger_info = LedgerInfo::new(
                 block_info,
@@ -284,93 +1394,860 @@ impl TransactionExecutor {
             let ledger_info_with_sigs =
                 LedgerInfoWithSignatures::new(ledger_info, BTreeMap::new() /* signatures */);
 
-            self.executor
+            let (committed_txns, reconfig_events) = self
+                .executor
                 .commit_blocks(vec![block_id], ledger_info_with_sigs)
                 .unwrap();
 
             self.parent_block_id = block_id;
-
             let commit_time = std::time::Instant::now().duration_since(commit_start);
-            let total_time = execute_time + commit_time;
 
+            // Not part of the commit, so it doesn't count towards the commit time.
+            if self.check_new_accounts {
+                if let Err(e) = self.check_accounts_exist(&new_accounts) {
+                    self.drain();
+                    return Err(e);
+                }
+            }
+
+            let callback_start = std::time::Instant::now();
+            if let Some(callback) = self.commit_callback.as_mut() {
+                callback(committed_txns, reconfig_events);
+            }
+            let callback_time = std::time::Instant::now().duration_since(callback_start);
+
+            let block_time = execute_time + commit_time + callback_time;
+            // The block metadata transaction takes a version too, but isn't part of the workload.
+            let num_accepted =
+                (version + 1 - first_version) as usize - usize::from(self.proposer.is_some());
+
+            if let Err(e) = self.update_write_set_digest(first_version, version, &touched_accounts)
+            {
+                self.drain();
+                return Err(e);
+            }
+
+            // The version is the ledger's, so it can be matched with node logs, and together with
+            // the digest it pins down the state after every block.
             info!(
-                "Version: {}. execute time: {} ms. commit time: {} ms. TPS: {}.",
+                "{}Version: {}.{} execute time: {} ms. commit time: {} ms.{} TPS: {}. \
+                 accepted: {}/{}.",
+                label
+                    .as_ref()
+                    .map_or_else(String::new, |label| format!("[{}] ", label)),
                 version,
+                self.write_set_digest
+                    .map_or_else(String::new, |digest| format!(" digest: {:x}.", digest)),
                 execute_time.as_millis(),
                 commit_time.as_millis(),
-                num_txns as u128 * 1_000_000_000 / total_time.as_nanos(),
+                if self.commit_callback.is_some() {
+                    format!(" callback time: {} ms.", callback_time.as_millis())
+                } else {
+                    String::new()
+                },
+                report::tps(num_txns, block_time),
+                num_accepted,
+                num_txns,
             );
+
+            total_txns += num_txns;
+            total_accepted += num_accepted;
+            total_time += block_time;
+            if let Some(label) = label {
+                let phase = report.phase_mut(&label);
+                phase.num_blocks += 1;
+                phase.num_txns += num_txns;
+                phase.num_accepted += num_accepted;
+                phase.num_bytes += num_bytes;
+                phase.time += block_time;
+                phase.execute_time += execute_time;
+                phase.commit_time += commit_time;
+                phase.callback_time += callback_time;
+            }
         }
-    }
-}
 
-fn create_storage_service_and_executor(
-    config: &NodeConfig,
-) -> (Arc<dyn DbReader>, Executor<DiemVM>) {
-    let (db, db_rw) = DbReaderWriter::wrap(
-        DiemDB::open(
-            &config.storage.dir(),
-            false, /* readonly */
-            None,  /* pruner */
-            RocksdbConfig::default(),
-        )
-        .expect("DB should open."),
-    );
-    let waypoint = generate_waypoint::<DiemVM>(&db_rw, get_genesis_txn(config).unwrap()).unwrap();
-    maybe_bootstrap::<DiemVM>(&db_rw, get_genesis_txn(config).unwrap(), waypoint).unwrap();
+        for phase in &report.phases {
+            info!(
+                "[{}] {} transactions in {} blocks in {} ms. TPS: {}. MB/s: {:.2}.",
+                phase.label,
+                phase.num_txns,
+                phase.num_blocks,
+                phase.time.as_millis(),
+                phase.tps(),
+                phase.mb_per_sec(),
+            );
+        }
 
-    let _handle = start_storage_service_with_db(config, db.clone());
-    let executor = Executor::new(
-        StorageClient::new(&config.storage.address, config.storage.timeout_ms).into(),
-    );
+        // The gross rate counts every submitted transaction, the effective one only those that
+        // made it into the ledger, i.e. not the ones rejected by the prologue.
+        info!(
+            "Accepted {} of {} transactions. gross TPS: {}. effective TPS: {}.",
+            total_accepted,
+            total_txns,
+            report::tps(total_txns, total_time),
+            report::tps(total_accepted, total_time),
+        );
 
-    (db, executor)
-}
+        info!("Committed up to version {}.", version);
 
-/// Runs the benchmark with given parameters.
+        report.committed_version = version;
+        report.write_set_digest = self.write_set_digest;
+        Ok(report)
+    }
+
+    /// Keeps draining the channel so the generator can run to completion instead of blocking on a
+    /// full channel once the executor has given up.
+    fn drain(&self) {
+        while self.block_receiver.recv().is_ok() {}
+    }
+
+    /// Returns an error listing the given accounts that don't exist in storage, if any.
+    fn check_accounts_exist(&self, accounts: &[AccountAddress]) -> Result<()> {
+        let mut missing = vec![];
+        for address in accounts {
+            if self.db.get_latest_account_state(*address)?.is_none() {
+                missing.push(*address);
+            }
+        }
+        if !missing.is_empty() {
+            bail!(
+                "{} of {} accounts were not created: {:?}",
+                missing.len(),
+                accounts.len(),
+                missing,
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns an error describing the first transaction of the block that was not executed
+    /// successfully, if any.
+    fn check_all_executed(
+        compute_status: &[TransactionStatus],
+        first_version: Version,
+    ) -> Result<()> {
+        let mut version = first_version;
+        for status in compute_status {
+            match status {
+                TransactionStatus::Keep(KeptVMStatus::Executed) => (),
+                _ => bail!(
+                    "Transaction at version {} was not executed successfully: {:?}",
+                    version,
+                    status,
+                ),
+            }
+            version += 1;
+        }
+        Ok(())
+    }
+
+    /// Folds the block committed as `first_version..=last_version` into the rolling digest.
+    ///
+    /// `BlockExecutor` doesn't surface the write sets, so each transaction contributes the event
+    /// root hash, gas used and status of its committed `TransactionInfo`, and what it wrote is
+    /// covered by the states of `touched_accounts` after the block. The transaction hash and the
+    /// state root are left out: the former covers the wall-clock based expiration time and the
+    /// latter the randomized genesis validator config, either of which differs from run to run.
+    fn update_write_set_digest(
+        &mut self,
+        first_version: Version,
+        last_version: Version,
+        touched_accounts: &BTreeSet<AccountAddress>,
+    ) -> Result<()> {
+        let digest = match self.write_set_digest.as_mut() {
+            Some(digest) => digest,
+            None => return Ok(()),
+        };
+
+        let mut start_version = first_version;
+        while start_version <= last_version {
+            let limit = std::cmp::min(MAX_TXNS_PER_QUERY, last_version - start_version + 1);
+            let txn_list = self.db.get_transactions(
+                start_version,
+                limit,
+                last_version,
+                false, /* fetch_events */
+            )?;
+            for txn_info in txn_list.proof.transaction_infos() {
+                let effects = TransactionInfo::new(
+                    HashValue::zero(), /* transaction_hash */
+                    HashValue::zero(), /* state_root_hash */
+                    txn_info.event_root_hash(),
+                    txn_info.gas_used(),
+                    txn_info.status().clone(),
+                );
+                *digest =
+                    HashValue::sha3_256_of(&[digest.to_vec(), effects.hash().to_vec()].concat());
+            }
+            start_version += limit;
+        }
+
+        for address in touched_accounts {
+            let state_hash = self
+                .db
+                .get_latest_account_state(*address)?
+                .map_or_else(HashValue::zero, |blob| blob.hash());
+            *digest = HashValue::sha3_256_of(
+                &[digest.to_vec(), address.to_vec(), state_hash.to_vec()].concat(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Accounts the user transactions of a block may write to: their senders and the addresses passed
+/// to their scripts, e.g. the receivers of transfers and the accounts being created.
+fn touched_accounts(transactions: &[Transaction]) -> BTreeSet<AccountAddress> {
+    let mut accounts = BTreeSet::new();
+    for txn in transactions {
+        if let Transaction::UserTransaction(txn) = txn {
+            accounts.insert(txn.sender());
+            if let TransactionPayload::Script(script) = txn.payload() {
+                for arg in script.args() {
+                    if let TransactionArgument::Address(address) = arg {
+                        accounts.insert(*address);
+                    }
+                }
+            }
+        }
+    }
+    accounts
+}
+
+/// Codes of the currencies registered on chain.
+fn get_registered_currency_codes(db: &dyn DbReader) -> Result<Vec<String>> {
+    let blob = db
+        .get_latest_account_state(diem_root_address())?
+        .ok_or_else(|| format_err!("Diem root account must exist."))?;
+    Ok(AccountState::try_from(&blob)?
+        .get_registered_currency_info_resources()?
+        .iter()
+        .map(|info| info.currency_code().to_string())
+        .collect())
+}
+
+/// Chain the DB was created for, as recorded at genesis.
+fn get_chain_id(db: &dyn DbReader) -> Result<ChainId> {
+    let blob = db
+        .get_latest_account_state(diem_root_address())?
+        .ok_or_else(|| format_err!("Diem root account must exist."))?;
+    Ok(AccountState::try_from(&blob)?
+        .get_chain_id_resource()?
+        .ok_or_else(|| format_err!("Chain id must be set at genesis."))?
+        .chain_id())
+}
+
+/// Largest transaction, in bytes, the VM accepts according to the on-chain gas schedule.
+fn get_max_transaction_size(db: &dyn DbReader) -> Result<u64> {
+    let blob = db
+        .get_latest_account_state(diem_root_address())?
+        .ok_or_else(|| format_err!("Diem root account must exist."))?;
+    let account_state = AccountState::try_from(&blob)?;
+    let vm_config = account_state
+        .get(&VMConfig::CONFIG_ID.access_path().path)
+        .ok_or_else(|| format_err!("VM config must be set at genesis."))?;
+    Ok(VMConfig::deserialize_into_config(vm_config)?
+        .gas_schedule
+        .gas_constants
+        .max_transaction_size_in_bytes)
+}
+
+/// Address of the first validator in the on-chain validator set.
+fn get_first_validator(db: &dyn DbReader) -> Result<AccountAddress> {
+    let blob = db
+        .get_latest_account_state(diem_root_address())?
+        .ok_or_else(|| format_err!("Diem root account must exist."))?;
+    AccountState::try_from(&blob)?
+        .get_validator_set()?
+        .and_then(|validator_set| {
+            validator_set
+                .payload()
+                .first()
+                .map(|validator| *validator.account_address())
+        })
+        .ok_or_else(|| format_err!("Validator set must not be empty."))
+}
+
+/// Opens the DB and starts a storage service and an executor on top of it. Genesis is only
+/// applied if `bootstrap` is set; otherwise the DB is used as is.
+///
+/// Failing to apply genesis is reported as such, with the VM status of the genesis transaction if
+/// it was executed, so it isn't mistaken for a failure of the benchmark's own transactions.
+fn create_storage_service_and_executor(
+    config: &NodeConfig,
+    bootstrap: bool,
+) -> Result<(Arc<dyn DbReader>, Executor<DiemVM>)> {
+    let (db, db_rw) = DbReaderWriter::wrap(
+        DiemDB::open(
+            &config.storage.dir(),
+            false, /* readonly */
+            None,  /* pruner */
+            RocksdbConfig::default(),
+        )
+        .expect("DB should open."),
+    );
+    if bootstrap {
+        let genesis_txn =
+            get_genesis_txn(config).ok_or_else(|| format_err!("No genesis transaction."))?;
+        let waypoint = match generate_waypoint::<DiemVM>(&db_rw, genesis_txn) {
+            Ok(waypoint) => waypoint,
+            Err(e) => {
+                // A failed genesis txn only shows as a missing epoch change, so look up its status.
+                check_genesis_status(&db_rw, genesis_txn)?;
+                return Err(e.context("Failed to execute the genesis transaction"));
+            }
+        };
+        maybe_bootstrap::<DiemVM>(&db_rw, genesis_txn, waypoint)
+            .context("Failed to commit the genesis transaction")?;
+    }
+
+    let _handle = start_storage_service_with_db(config, db.clone());
+    let executor = Executor::new(
+        StorageClient::new(&config.storage.address, config.storage.timeout_ms).into(),
+    );
+
+    Ok((db, executor))
+}
+
+/// Executes `genesis_txn` on top of the unbootstrapped DB, without committing it, and fails with
+/// its status if it isn't executed successfully.
+fn check_genesis_status(db_rw: &DbReaderWriter, genesis_txn: &Transaction) -> Result<()> {
+    let tree_state = db_rw.reader.get_latest_tree_state()?;
+    let mut executor = Executor::<DiemVM>::new_on_unbootstrapped_db(db_rw.clone(), tree_state);
+    let result = executor.execute_block(
+        (HashValue::zero(), vec![genesis_txn.clone()]),
+        *PRE_GENESIS_BLOCK_ID,
+    )?;
+    if let Some(status) = result.compute_status().first() {
+        if *status != TransactionStatus::Keep(KeptVMStatus::Executed) {
+            bail!("Genesis transaction failed: {:?}.", status);
+        }
+    }
+    Ok(())
+}
+
+/// A finished benchmark run, with the database it committed to still open so callers can run
+/// their own checks against the final state.
+pub struct BenchmarkRun {
+    pub report: BenchmarkReport,
+    pub db: Arc<dyn DbReader>,
+    /// The generator, holding the accounts and their expected sequence numbers, for use with its
+    /// `verify_*` methods.
+    pub generator: TransactionGenerator,
+    /// Owns the temporary directory the database lives in, unless a `db_dir` was given.
+    _config: NodeConfig,
+}
+
+/// Runs the benchmark with given parameters and returns a summary of the run.
+///
+/// Every transfer is between two distinct accounts of the same generator, so transfers need at
+/// least two accounts per generator, i.e. `num_accounts >= 2` with the default single generator.
 pub fn run_benchmark(
     num_accounts: usize,
     init_account_balance: u64,
     block_size: usize,
     num_transfer_blocks: usize,
     db_dir: Option<PathBuf>,
-) {
-    let (mut config, genesis_key) = diem_genesis_tool::test_config();
+    opt: BenchmarkOpt,
+) -> Result<BenchmarkReport> {
+    run_benchmark_and_keep_db(
+        num_accounts,
+        init_account_balance,
+        block_size,
+        num_transfer_blocks,
+        db_dir,
+        opt,
+        None, /* commit_callback */
+    )
+    .map(|run| run.report)
+}
+
+/// Runs `num_workloads` benchmarks at the same time, each with its own generator, executor and
+/// fresh database, and returns their reports in order. Unlike a single workload, this exercises
+/// state shared by all executors in the process, such as the VM's caches, for hidden contention.
+///
+/// Every workload's throughput is logged at the end, followed by the aggregate: the transactions
+/// accepted by all of them over the longest wall time.
+pub fn run_concurrent_benchmarks(
+    num_workloads: usize,
+    num_accounts: usize,
+    init_account_balance: u64,
+    block_size: usize,
+    num_transfer_blocks: usize,
+    opt: BenchmarkOpt,
+) -> Result<Vec<BenchmarkReport>> {
+    if num_workloads == 0 {
+        bail!("Number of workloads must be positive.");
+    }
+    if opt.replay_from_version.is_some() {
+        bail!("Concurrent workloads each start from genesis, so they can't replay.");
+    }
+
+    // The allocation counters are process-wide, so only the workloads' combined allocations can
+    // be measured.
+    #[cfg(feature = "alloc-stats")]
+    let alloc_start = AllocationStats::start();
+    let handles: Vec<_> = (0..num_workloads)
+        .map(|i| {
+            let opt = opt.clone();
+            std::thread::Builder::new()
+                .name(format!("workload_{}", i))
+                .spawn(move || {
+                    run_workload(
+                        num_accounts,
+                        init_account_balance,
+                        block_size,
+                        num_transfer_blocks,
+                        None, /* db_dir */
+                        opt,
+                        None,  /* commit_callback */
+                        false, /* measure_allocations */
+                    )
+                    .map(|run| run.report)
+                })
+                .expect("Failed to spawn workload thread.")
+        })
+        .collect();
+    let reports = handles
+        .into_iter()
+        .enumerate()
+        .map(|(i, handle)| {
+            handle
+                .join()
+                .map_err(|_| format_err!("Workload {} panicked.", i))?
+        })
+        .collect::<Result<Vec<_>>>()?;
+    #[cfg(feature = "alloc-stats")]
+    log_allocation_stats(&alloc_start.since());
+
+    for (i, report) in reports.iter().enumerate() {
+        info!(
+            "Workload {}: accepted {} of {} transactions in {} ms. TPS: {}.",
+            i,
+            report.total_accepted(),
+            report.total_txns(),
+            report.wall_time.as_millis(),
+            report::tps(report.total_accepted(), report.wall_time),
+        );
+    }
+    let total_accepted: usize = reports.iter().map(BenchmarkReport::total_accepted).sum();
+    let total_txns: usize = reports.iter().map(BenchmarkReport::total_txns).sum();
+    let wall_time = reports
+        .iter()
+        .map(|report| report.wall_time)
+        .max()
+        .unwrap_or_default();
+    info!(
+        "{} workloads: accepted {} of {} transactions in {} ms. aggregate TPS: {}.",
+        num_workloads,
+        total_accepted,
+        total_txns,
+        wall_time.as_millis(),
+        report::tps(total_accepted, wall_time),
+    );
+
+    Ok(reports)
+}
+
+/// Same as `run_benchmark`, but keeps the database open after the run instead of dropping it.
+///
+/// `commit_callback`, if given, is called after every commit with what was committed, to account
+/// for the work a node does downstream of the executor, e.g. notifying state sync.
+pub fn run_benchmark_and_keep_db(
+    num_accounts: usize,
+    init_account_balance: u64,
+    block_size: usize,
+    num_transfer_blocks: usize,
+    db_dir: Option<PathBuf>,
+    opt: BenchmarkOpt,
+    commit_callback: Option<CommitCallback>,
+) -> Result<BenchmarkRun> {
+    run_workload(
+        num_accounts,
+        init_account_balance,
+        block_size,
+        num_transfer_blocks,
+        db_dir,
+        opt,
+        commit_callback,
+        true, /* measure_allocations */
+    )
+}
+
+/// Same as `run_benchmark_and_keep_db`, but only measures the allocations of the run if
+/// `measure_allocations` is set. The allocation counters are process-wide, so they can't be
+/// attributed to one of several workloads running at the same time.
+fn run_workload(
+    num_accounts: usize,
+    init_account_balance: u64,
+    block_size: usize,
+    num_transfer_blocks: usize,
+    db_dir: Option<PathBuf>,
+    opt: BenchmarkOpt,
+    commit_callback: Option<CommitCallback>,
+    #[cfg_attr(not(feature = "alloc-stats"), allow(unused_variables))] measure_allocations: bool,
+) -> Result<BenchmarkRun> {
+    if block_size == 0 {
+        bail!("Block size must be positive.");
+    }
+    if opt.num_generators == 0 {
+        bail!("Number of generators must be positive.");
+    }
+    if opt.num_validators == 0 {
+        bail!("Number of validators must be positive.");
+    }
+    if opt.run_timeout_secs == Some(0) {
+        bail!("Run timeout must be positive.");
+    }
+    if opt.transfer_duration_secs == Some(0) {
+        bail!("Transfer duration must be positive.");
+    }
+    let has_transfers = num_transfer_blocks > 0 || opt.transfer_duration_secs.is_some();
+    if has_transfers && num_accounts < 2 * opt.num_generators {
+        bail!(
+            "Transfers need at least two accounts for each of the {} generators, but there are {}.",
+            opt.num_generators,
+            num_accounts,
+        );
+    }
+    if num_accounts < block_size {
+        warn!(
+            "Only {} accounts for blocks of {} transactions: many transfers in a block will share \
+             their sender with another one.",
+            num_accounts, block_size,
+        );
+    }
+
+    if !(0.0..=1.0).contains(&opt.expired_fraction) {
+        bail!(
+            "Expired fraction must be between 0 and 1, got {}.",
+            opt.expired_fraction
+        );
+    }
+    if opt.strict && opt.expired_fraction > 0.0 {
+        bail!("Expired transactions are never executed, which fails a strict run.");
+    }
+    if opt.transfer_block_sizes.contains(&0) {
+        bail!("Transfer block sizes must be positive.");
+    }
+    if let Some(target_tps) = opt.target_tps {
+        if target_tps < opt.num_generators as u64 {
+            bail!(
+                "Target TPS {} is below the number of generators {}, which each need a share.",
+                target_tps,
+                opt.num_generators,
+            );
+        }
+    }
+    if opt.write_set_digest || opt.expected_write_set_digest.is_some() {
+        if opt.num_generators > 1 {
+            bail!(
+                "Blocks of several generators interleave differently every run, so a write set \
+                 digest isn't reproducible."
+            );
+        }
+        if opt.transfer_duration_secs.is_some() {
+            bail!(
+                "The number of blocks in a transfer duration differs every run, so a write set \
+                 digest isn't reproducible."
+            );
+        }
+    }
+    if opt.unique_senders && has_transfers {
+        let max_block_size = opt
+            .transfer_block_sizes
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(block_size);
+        // The smallest range of accounts a generator gets.
+        let accounts_per_generator = num_accounts / opt.num_generators;
+        if max_block_size > accounts_per_generator {
+            bail!(
+                "Unique senders need {} accounts per generator, but there are only {}.",
+                max_block_size,
+                accounts_per_generator,
+            );
+        }
+    }
+    if opt.script.is_none() && !opt.script_args.is_empty() {
+        bail!("Script arguments were given without a script.");
+    }
+    // Load the script before setting anything up, so a bad one fails fast.
+    let script = opt
+        .script
+        .as_ref()
+        .map(|path| ScriptTemplate::load(path, &opt.script_args))
+        .transpose()?;
+    if opt.replay_from_version.is_some() && db_dir.is_none() {
+        bail!("Replaying needs the existing database to be passed as db_dir.");
+    }
+    if opt.replay_from_version.is_some() && opt.mint_distribution != MintDistribution::Fixed {
+        bail!("Replaying doesn't mint, so it can't use a mint distribution.");
+    }
+
+    let (mut config, genesis_key) =
+        diem_genesis_tool::test_config_with_num_validators(opt.num_validators);
     if let Some(path) = db_dir {
         config.storage.dir = path;
     }
 
-    let (db, executor) = create_storage_service_and_executor(&config);
+    let (db, executor) =
+        create_storage_service_and_executor(&config, opt.replay_from_version.is_none())?;
+    let start_version = db.get_latest_version()?;
+    if let Some(version) = opt.replay_from_version {
+        if start_version != version {
+            bail!(
+                "Database is at version {}, not at version {}.",
+                start_version,
+                version,
+            );
+        }
+        info!("Replaying from version {}.", start_version);
+    }
     let parent_block_id = executor.committed_block_id();
+    let executor_db = db.clone();
+    let record_write_set_digest = opt.write_set_digest || opt.expected_write_set_digest.is_some();
 
+    let chain_timestamp_usecs = if opt.expiration_from_chain_time {
+        Some(db.get_latest_commit_metadata()?.1)
+    } else {
+        None
+    };
+
+    let num_generators = opt.num_generators;
+    let strict = opt.strict;
+    let check_new_accounts = !opt.skip_account_creation_check;
+    let proposer = if opt.block_metadata {
+        Some(get_first_validator(db.as_ref())?)
+    } else {
+        None
+    };
+    let replay = opt.replay_from_version.is_some();
+    let transfer_block_sizes = opt.transfer_block_sizes.clone();
     let (block_sender, block_receiver) = mpsc::sync_channel(50 /* bound */);
 
+    let mut generator = if replay {
+        TransactionGenerator::new_with_db_state(
+            genesis_key,
+            num_accounts,
+            block_sender,
+            db.as_ref(),
+        )?
+    } else {
+        TransactionGenerator::new(genesis_key, num_accounts, block_sender)
+    };
+    if let Some(timestamp_usecs) = chain_timestamp_usecs {
+        generator.use_chain_timestamp(timestamp_usecs);
+    }
+    generator.set_expired_fraction(opt.expired_fraction);
+    let registered_currencies = get_registered_currency_codes(db.as_ref())?;
+    if !registered_currencies.contains(&opt.gas_currency) {
+        bail!(
+            "Gas currency {} is not registered, expected one of {:?}.",
+            opt.gas_currency,
+            registered_currencies,
+        );
+    }
+    if replay {
+        generator.verify_currency(db.as_ref(), &opt.gas_currency)?;
+    }
+    generator.set_gas_currency(&opt.gas_currency);
+    generator.set_chain_id(get_chain_id(db.as_ref())?);
+    generator.set_unique_senders(opt.unique_senders);
+    if let Some(target_tps) = opt.target_tps {
+        generator.set_target_tps(target_tps);
+    }
+    generator.set_padding_bytes(opt.txn_padding_bytes);
+    if opt.txn_padding_bytes > 0 && script.is_none() {
+        let transfer_size = generator.transfer_size();
+        let max_transaction_size = get_max_transaction_size(db.as_ref())?;
+        if transfer_size as u64 > max_transaction_size {
+            bail!(
+                "Transfers padded with {} bytes take {} bytes, above the maximum transaction size \
+                 of {} bytes.",
+                opt.txn_padding_bytes,
+                transfer_size,
+                max_transaction_size,
+            );
+        }
+    }
+    if let Some(secs) = opt.transfer_duration_secs {
+        generator.set_transfer_duration(Duration::from_secs(secs));
+    }
+    let has_script = script.is_some();
+    if let Some(script) = script {
+        generator.set_script(script);
+    }
+    if let Some(path) = &opt.accounts_file {
+        generator.write_accounts(path)?;
+        info!("Wrote {} accounts to {}.", num_accounts, path.display());
+    }
+    let expected_total_balance = if replay {
+        generator.total_balance(db.as_ref())?
+    } else {
+        generator.set_mint_distribution(opt.mint_distribution, init_account_balance);
+        generator.total_mint_amount(init_account_balance)
+    };
+
+    #[cfg(feature = "alloc-stats")]
+    let alloc_start = if measure_allocations {
+        Some(AllocationStats::start())
+    } else {
+        None
+    };
+    let start = std::time::Instant::now();
+    let deadline = opt
+        .run_timeout_secs
+        .map(|secs| start + Duration::from_secs(secs));
     // Spawn two threads to run transaction generator and executor separately.
-    let gen_thread = std::thread::Builder::new()
-        .name("txn_generator".to_string())
-        .spawn(move || {
-            let mut generator = TransactionGenerator::new(genesis_key, num_accounts, block_sender);
-            generator.run(init_account_balance, block_size, num_transfer_blocks);
-            generator
-        })
-        .expect("Failed to spawn transaction generator thread.");
-    let exe_thread = std::thread::Builder::new()
-        .name("txn_executor".to_string())
-        .spawn(move || {
-            let mut exe = TransactionExecutor::new(executor, parent_block_id, block_receiver);
-            exe.run();
-        })
-        .expect("Failed to spawn transaction executor thread.");
+    let gen_thread = WatchedThread::spawn("txn_generator", move || {
+        if replay {
+            generator.run_transfers(
+                block_size,
+                num_transfer_blocks,
+                num_generators,
+                &transfer_block_sizes,
+            );
+        } else {
+            generator.run(
+                init_account_balance,
+                block_size,
+                num_transfer_blocks,
+                num_generators,
+                &transfer_block_sizes,
+            );
+        }
+        generator
+    });
+    let exe_thread = WatchedThread::spawn("txn_executor", move || {
+        let mut exe = TransactionExecutor::new(
+            executor,
+            parent_block_id,
+            block_receiver,
+            executor_db,
+            record_write_set_digest,
+            strict,
+            check_new_accounts,
+            proposer,
+            commit_callback,
+        );
+        exe.run()
+    });
 
     // Wait for generator to finish and get back the generator.
-    let mut generator = gen_thread.join().unwrap();
+    let mut generator = gen_thread.join(deadline)?;
     // Drop the sender so the executor thread can eventually exit.
     generator.drop_sender();
     // Wait until all transactions are committed.
-    exe_thread.join().unwrap();
+    let mut report = exe_thread.join(deadline)??;
+    report.num_accounts = num_accounts;
+    report.wall_time = std::time::Instant::now().duration_since(start);
+    #[cfg(feature = "alloc-stats")]
+    {
+        if let Some(alloc_start) = alloc_start {
+            log_allocation_stats(&alloc_start.since());
+        }
+    }
+
+    if let Some(digest) = report.write_set_digest {
+        info!("Write set digest: {:x}", digest);
+        if let Some(expected) = opt.expected_write_set_digest {
+            if digest != expected {
+                bail!(
+                    "Write set digest {:x} doesn't match the expected {:x}, execution is \
+                     nondeterministic.",
+                    digest,
+                    expected,
+                );
+            }
+        }
+    }
 
     // Do a sanity check on the sequence number to make sure all transactions are committed.
     generator.verify_sequence_number(db.as_ref());
+    let end_version = db.get_latest_version()?;
+    if end_version > start_version {
+        generator.verify_event_streams(db.as_ref(), start_version + 1, end_version)?;
+    }
+    if has_script {
+        info!("Skipping the balance check, the custom script may have moved funds.");
+    } else {
+        generator.verify_balances(db.as_ref(), expected_total_balance)?;
+    }
+
+    info!(
+        "Started at version {}, ended at version {}.",
+        start_version, end_version,
+    );
+
+    Ok(BenchmarkRun {
+        report,
+        db,
+        generator,
+        _config: config,
+    })
+}
+
+#[cfg(feature = "alloc-stats")]
+fn log_allocation_stats(alloc_stats: &AllocationStats) {
+    info!(
+        "Allocated {} bytes in {} allocations, freed {} bytes. peak live bytes: {}.",
+        alloc_stats.bytes_allocated,
+        alloc_stats.num_allocations,
+        alloc_stats.bytes_freed,
+        alloc_stats.peak_live_bytes,
+    );
+}
+
+/// Measures signature verification throughput in isolation: blocks of transfer transactions are
+/// built up front and then only have their signatures checked, in parallel within each block, the
+/// same way the VM verifies a block before executing it.
+///
+/// Every transfer is between two distinct accounts, so `num_accounts` must be at least two.
+pub fn run_signature_verification_benchmark(
+    num_accounts: usize,
+    block_size: usize,
+    num_blocks: usize,
+) -> Result<()> {
+    if num_accounts < 2 {
+        bail!(
+            "Transfers need at least two accounts, but there are {}.",
+            num_accounts
+        );
+    }
+    // Signatures can be checked without any state, so there is no genesis or DB to set up; the
+    // key is only needed to construct the generator.
+    let genesis_key = Ed25519PrivateKey::generate(&mut StdRng::from_seed([0u8; 32]));
+    let (block_sender, block_receiver) = mpsc::sync_channel(num_blocks);
+    let mut generator = TransactionGenerator::new(genesis_key, num_accounts, block_sender);
+    generator.gen_transfer_transactions("transfer", block_size, num_blocks);
+    generator.drop_sender();
+    let blocks: Vec<Vec<Transaction>> = block_receiver
+        .iter()
+        .map(|block| block.transactions)
+        .collect();
+
+    let num_txns: usize = blocks.iter().map(Vec::len).sum();
+    let verify_start = std::time::Instant::now();
+    for block in blocks {
+        block.into_par_iter().for_each(|txn| {
+            if let Transaction::UserTransaction(signed_txn) = txn {
+                signed_txn
+                    .check_signature()
+                    .expect("Generated transaction must have a valid signature.");
+            }
+        });
+    }
+    let verify_time = std::time::Instant::now().duration_since(verify_start);
+
+    info!(
+        "Verified {} signatures in {} ms. Signatures per second: {}.",
+        num_txns,
+        verify_time.as_millis(),
+        num_txns as u128 * 1_000_000_000 / std::cmp::max(verify_time.as_nanos(), 1),
+    );
+    Ok(())
 }
 
 fn create_transaction(
@@ -379,19 +2256,19 @@ fn create_transaction(
     private_key: &Ed25519PrivateKey,
     public_key: Ed25519PublicKey,
     program: Script,
+    expiration_time: u64,
+    gas_currency_code: &str,
+    chain_id: ChainId,
 ) -> Transaction {
-    let now = diem_infallible::duration_since_epoch();
-    let expiration_time = now.as_secs() + 3600;
-
     let raw_txn = RawTransaction::new_script(
         sender,
         sequence_number,
         program,
-        1_000_000,           /* max_gas_amount */
-        0,                   /* gas_unit_price */
-        XUS_NAME.to_owned(), /* gas_currency_code */
+        1_000_000, /* max_gas_amount */
+        0,         /* gas_unit_price */
+        gas_currency_code.to_owned(),
         expiration_time,
-        ChainId::test(),
+        chain_id,
     );
 
     let signature = private_key.sign(&raw_txn);
@@ -409,6 +2286,536 @@ mod tests {
             5,    /* block_size */
             5,    /* num_transfer_blocks */
             None, /* db_dir */
+            super::BenchmarkOpt::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_benchmark_concurrent_generators() {
+        super::run_benchmark(
+            25,   /* num_accounts */
+            10,   /* init_account_balance */
+            5,    /* block_size */
+            5,    /* num_transfer_blocks */
+            None, /* db_dir */
+            super::BenchmarkOpt {
+                num_generators: 3,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_benchmark_keeps_db() {
+        let run = super::run_benchmark_and_keep_db(
+            25,   /* num_accounts */
+            10,   /* init_account_balance */
+            5,    /* block_size */
+            5,    /* num_transfer_blocks */
+            None, /* db_dir */
+            super::BenchmarkOpt::default(),
+            None, /* commit_callback */
+        )
+        .unwrap();
+        run.generator.verify_sequence_number(run.db.as_ref());
+        run.generator
+            .verify_balances(run.db.as_ref(), 250 /* expected_total */)
+            .unwrap();
+        assert!(run
+            .generator
+            .verify_balances(run.db.as_ref(), 251 /* expected_total */)
+            .is_err());
+    }
+
+    #[test]
+    fn test_block_metadata() {
+        let run = |block_metadata| {
+            super::run_benchmark(
+                25,   /* num_accounts */
+                10,   /* init_account_balance */
+                5,    /* block_size */
+                5,    /* num_transfer_blocks */
+                None, /* db_dir */
+                super::BenchmarkOpt {
+                    block_metadata,
+                    strict: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+        let without = run(false);
+        let with = run(true);
+        // Every block takes one more version, which isn't counted towards the workload.
+        let num_blocks: usize = with.phases.iter().map(|phase| phase.num_blocks).sum();
+        assert_eq!(
+            with.committed_version,
+            without.committed_version + num_blocks as u64
+        );
+        assert_eq!(with.total_accepted(), without.total_accepted());
+        assert_eq!(with.total_txns(), without.total_txns());
+    }
+
+    #[test]
+    fn test_expired_fraction() {
+        let report = super::run_benchmark(
+            25,   /* num_accounts */
+            10,   /* init_account_balance */
+            5,    /* block_size */
+            5,    /* num_transfer_blocks */
+            None, /* db_dir */
+            super::BenchmarkOpt {
+                expired_fraction: 1.0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let transfer = report.phase("transfer").unwrap();
+        assert_eq!(transfer.num_txns, 25);
+        assert_eq!(transfer.num_accepted, 0);
+    }
+
+    #[test]
+    fn test_transfer_duration() {
+        let report = super::run_benchmark(
+            25,   /* num_accounts */
+            10,   /* init_account_balance */
+            5,    /* block_size */
+            0,    /* num_transfer_blocks, ignored */
+            None, /* db_dir */
+            super::BenchmarkOpt {
+                transfer_duration_secs: Some(1),
+                // A block every 250 ms, so the second bounds the number of blocks.
+                target_tps: Some(20),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let transfer = report.phase("transfer").unwrap();
+        assert!(
+            (1..=5).contains(&transfer.num_blocks),
+            "{} blocks",
+            transfer.num_blocks
+        );
+    }
+
+    #[test]
+    fn test_txn_padding() {
+        let run = |txn_padding_bytes| {
+            super::run_benchmark(
+                25,   /* num_accounts */
+                10,   /* init_account_balance */
+                5,    /* block_size */
+                5,    /* num_transfer_blocks */
+                None, /* db_dir */
+                super::BenchmarkOpt {
+                    txn_padding_bytes,
+                    strict: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+        let unpadded = run(0);
+        let padded = run(100);
+        // Padding only goes into the transfers, and doesn't stop them from executing.
+        let unpadded = unpadded.phase("transfer").unwrap();
+        let padded = padded.phase("transfer").unwrap();
+        assert_eq!(padded.num_accepted, unpadded.num_accepted);
+        assert!(
+            padded.num_bytes >= unpadded.num_bytes + 100 * padded.num_txns,
+            "{} bytes padded, {} bytes unpadded",
+            padded.num_bytes,
+            unpadded.num_bytes
         );
+
+        // Transfers above the maximum transaction size would be rejected, so the run is too.
+        assert!(super::run_benchmark(
+            25,   /* num_accounts */
+            10,   /* init_account_balance */
+            5,    /* block_size */
+            5,    /* num_transfer_blocks */
+            None, /* db_dir */
+            super::BenchmarkOpt {
+                txn_padding_bytes: 4096,
+                ..Default::default()
+            },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_strict_benchmark() {
+        let run = |init_account_balance| {
+            super::run_benchmark(
+                25, /* num_accounts */
+                init_account_balance,
+                5,    /* block_size */
+                5,    /* num_transfer_blocks */
+                None, /* db_dir */
+                super::BenchmarkOpt {
+                    strict: true,
+                    ..Default::default()
+                },
+            )
+        };
+        run(10).unwrap();
+        // Minting zero coins aborts, which fails a strict run.
+        let error = run(0).unwrap_err().to_string();
+        assert!(error.contains("was not executed successfully"), "{}", error);
+    }
+
+    #[test]
+    fn test_write_set_digest_is_deterministic() {
+        let run = |expected_write_set_digest| {
+            super::run_benchmark(
+                25,   /* num_accounts */
+                10,   /* init_account_balance */
+                5,    /* block_size */
+                5,    /* num_transfer_blocks */
+                None, /* db_dir */
+                super::BenchmarkOpt {
+                    write_set_digest: true,
+                    expected_write_set_digest,
+                    ..Default::default()
+                },
+            )
+        };
+        let digest = run(None).unwrap().write_set_digest.unwrap();
+        assert_eq!(run(Some(digest)).unwrap().write_set_digest, Some(digest));
+        assert!(run(Some(super::HashValue::zero())).is_err());
+
+        // Workloads that differ from run to run can't have a digest.
+        for opt in &[
+            super::BenchmarkOpt {
+                num_generators: 3,
+                ..Default::default()
+            },
+            super::BenchmarkOpt {
+                transfer_duration_secs: Some(1),
+                ..Default::default()
+            },
+        ] {
+            assert!(super::run_benchmark(
+                25,   /* num_accounts */
+                10,   /* init_account_balance */
+                5,    /* block_size */
+                5,    /* num_transfer_blocks */
+                None, /* db_dir */
+                super::BenchmarkOpt {
+                    write_set_digest: true,
+                    ..opt.clone()
+                },
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        assert!(super::run_benchmark(
+            25,   /* num_accounts */
+            10,   /* init_account_balance */
+            5,    /* block_size */
+            5,    /* num_transfer_blocks */
+            None, /* db_dir */
+            super::BenchmarkOpt {
+                num_validators: 0,
+                ..Default::default()
+            },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_replay_picks_up_db_state() {
+        use super::*;
+
+        // A replay run can't reopen the database of an earlier run in the same process, since the
+        // storage service keeps it open, so check the state it resumes from here. Replays run in
+        // their own process in tests/replay.rs.
+        let run = run_benchmark_and_keep_db(
+            25,   /* num_accounts */
+            10,   /* init_account_balance */
+            5,    /* block_size */
+            5,    /* num_transfer_blocks */
+            None, /* db_dir */
+            BenchmarkOpt::default(),
+            None, /* commit_callback */
+        )
+        .unwrap();
+        let genesis_key = Ed25519PrivateKey::generate(&mut StdRng::from_seed([0u8; 32]));
+        let (block_sender, _block_receiver) = mpsc::sync_channel(1);
+        let generator =
+            TransactionGenerator::new_with_db_state(genesis_key, 25, block_sender, run.db.as_ref())
+                .unwrap();
+        let sequence_numbers = |generator: &TransactionGenerator| {
+            generator
+                .accounts
+                .iter()
+                .map(|account| account.sequence_number)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sequence_numbers(&generator),
+            sequence_numbers(&run.generator)
+        );
+        assert_eq!(sequence_numbers(&generator).iter().sum::<u64>(), 25);
+        assert_eq!(generator.total_balance(run.db.as_ref()).unwrap(), 250);
+
+        // Replaying needs an existing database.
+        assert!(run_benchmark(
+            25,   /* num_accounts */
+            10,   /* init_account_balance */
+            5,    /* block_size */
+            5,    /* num_transfer_blocks */
+            None, /* db_dir */
+            BenchmarkOpt {
+                replay_from_version: Some(run.report.committed_version),
+                ..Default::default()
+            },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_concurrent_benchmarks() {
+        let reports = super::run_concurrent_benchmarks(
+            2,  /* num_workloads */
+            25, /* num_accounts */
+            10, /* init_account_balance */
+            5,  /* block_size */
+            5,  /* num_transfer_blocks */
+            super::BenchmarkOpt::default(),
+        )
+        .unwrap();
+        assert_eq!(reports.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_mint_distribution() {
+        use super::MintDistribution;
+
+        assert_eq!(
+            "fixed".parse::<MintDistribution>().unwrap(),
+            MintDistribution::Fixed
+        );
+        assert_eq!(
+            "uniform:10:20".parse::<MintDistribution>().unwrap(),
+            MintDistribution::Uniform { min: 10, max: 20 }
+        );
+        assert_eq!(
+            "pareto:10:1000:1.5".parse::<MintDistribution>().unwrap(),
+            MintDistribution::Pareto {
+                min: 10,
+                max: 1000,
+                alpha: 1.5
+            }
+        );
+        assert!("uniform:20:10".parse::<MintDistribution>().is_err());
+        assert!("pareto:0:10:1".parse::<MintDistribution>().is_err());
+        assert!("pareto:1:10:0".parse::<MintDistribution>().is_err());
+        assert!("normal".parse::<MintDistribution>().is_err());
+    }
+
+    #[test]
+    fn test_reset_generator() {
+        use super::*;
+
+        fn gen_blocks(
+            generator: &mut TransactionGenerator,
+            block_receiver: &mpsc::Receiver<TransactionBlock>,
+        ) -> Vec<Vec<Transaction>> {
+            generator.gen_transfer_transactions(
+                "transfer", /* label */
+                5,          /* block_size */
+                3,          /* num_blocks */
+            );
+            block_receiver
+                .try_iter()
+                .map(|block| block.transactions)
+                .collect()
+        }
+
+        let new_generator = |block_sender| {
+            let genesis_key = Ed25519PrivateKey::generate(&mut StdRng::from_seed([0u8; 32]));
+            let mut generator = TransactionGenerator::new(genesis_key, 10, block_sender);
+            // Keep the expiration times, and with them the transactions, off the local clock.
+            generator.use_chain_timestamp(0);
+            generator
+        };
+
+        let (block_sender, block_receiver) = mpsc::sync_channel(3);
+        let mut fresh = new_generator(block_sender);
+        let fresh_blocks = gen_blocks(&mut fresh, &block_receiver);
+
+        let (block_sender, block_receiver) = mpsc::sync_channel(3);
+        let mut reset = new_generator(block_sender);
+        let first_blocks = gen_blocks(&mut reset, &block_receiver);
+        let (block_sender, block_receiver) = mpsc::sync_channel(3);
+        reset.reset(block_sender);
+        let reset_blocks = gen_blocks(&mut reset, &block_receiver);
+
+        assert_eq!(first_blocks, fresh_blocks);
+        assert_eq!(reset_blocks, fresh_blocks);
+
+        // Drawing the mint amounts doesn't change the transfers either.
+        let (block_sender, block_receiver) = mpsc::sync_channel(3);
+        let mut minting = new_generator(block_sender);
+        minting.set_mint_distribution(MintDistribution::Uniform { min: 1, max: 100 }, 10);
+        assert_eq!(gen_blocks(&mut minting, &block_receiver), fresh_blocks);
+    }
+
+    #[test]
+    fn test_unique_senders() {
+        use super::*;
+
+        let genesis_key = Ed25519PrivateKey::generate(&mut StdRng::from_seed([0u8; 32]));
+        let (block_sender, block_receiver) = mpsc::sync_channel(5);
+        let mut generator = TransactionGenerator::new(genesis_key, 10, block_sender);
+        generator.set_unique_senders(true);
+        generator.gen_transfer_transactions(
+            "transfer", /* label */
+            10,         /* block_size */
+            5,          /* num_blocks */
+        );
+        generator.drop_sender();
+
+        for block in block_receiver.iter() {
+            let senders: BTreeSet<_> = block
+                .transactions
+                .iter()
+                .map(|txn| match txn {
+                    Transaction::UserTransaction(txn) => txn.sender(),
+                    _ => panic!("Expected only user transactions."),
+                })
+                .collect();
+            assert_eq!(senders.len(), 10);
+        }
+    }
+
+    #[test]
+    fn test_split_target_tps() {
+        use super::*;
+
+        let genesis_key = Ed25519PrivateKey::generate(&mut StdRng::from_seed([0u8; 32]));
+        let (block_sender, _block_receiver) = mpsc::sync_channel(1);
+        let mut generator = TransactionGenerator::new(genesis_key, 10, block_sender);
+        generator.set_target_tps(7);
+        let target_tps: Vec<_> = generator
+            .split(3)
+            .iter()
+            .map(|generator| generator.target_tps)
+            .collect();
+        assert_eq!(target_tps, vec![Some(3), Some(2), Some(2)]);
+
+        assert!(run_benchmark(
+            25,   /* num_accounts */
+            10,   /* init_account_balance */
+            5,    /* block_size */
+            5,    /* num_transfer_blocks */
+            None, /* db_dir */
+            BenchmarkOpt {
+                num_generators: 3,
+                target_tps: Some(2),
+                ..Default::default()
+            },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_script_transfers() {
+        use super::*;
+
+        // Any compiled script will do, since the transactions aren't executed.
+        let code = transaction_builder::encode_freeze_account_script(0, AccountAddress::ZERO)
+            .code()
+            .to_vec();
+        let script = ScriptTemplate::new(
+            code.clone(),
+            vec![
+                ScriptArgument::Value(TransactionArgument::U64(1)),
+                ScriptArgument::Receiver,
+            ],
+        )
+        .unwrap();
+
+        let genesis_key = Ed25519PrivateKey::generate(&mut StdRng::from_seed([0u8; 32]));
+        let (block_sender, block_receiver) = mpsc::sync_channel(2);
+        let mut generator = TransactionGenerator::new(genesis_key, 10, block_sender);
+        generator.set_script(script);
+        generator.run_transfers(
+            5,   /* block_size */
+            2,   /* num_transfer_blocks */
+            1,   /* num_generators */
+            &[], /* transfer_block_sizes */
+        );
+        generator.drop_sender();
+
+        for block in block_receiver.iter() {
+            assert_eq!(block.label.as_deref(), Some("script"));
+            for txn in block.transactions {
+                let txn = match txn {
+                    Transaction::UserTransaction(txn) => txn,
+                    _ => panic!("Expected only user transactions."),
+                };
+                let script = match txn.payload() {
+                    TransactionPayload::Script(script) => script,
+                    _ => panic!("Expected a script."),
+                };
+                assert_eq!(script.code(), code.as_slice());
+                match script.args() {
+                    [TransactionArgument::U64(1), TransactionArgument::Address(receiver)] => {
+                        assert_ne!(*receiver, txn.sender())
+                    }
+                    args => panic!("Unexpected arguments {:?}.", args),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_watched_thread_timeout() {
+        use super::*;
+        use std::time::Instant;
+
+        let deadline = || Some(Instant::now() + Duration::from_millis(100));
+        assert_eq!(
+            WatchedThread::spawn("quick", || 1)
+                .join(deadline())
+                .unwrap(),
+            1
+        );
+
+        let error = WatchedThread::spawn("stuck", || std::thread::sleep(Duration::from_secs(2)))
+            .join(deadline())
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("stuck"), "{}", error);
+
+        let error = WatchedThread::spawn("panicking", || panic!("Injected panic."))
+            .join(deadline())
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("panicked"), "{}", error);
+    }
+
+    #[test]
+    fn test_signature_verification_benchmark() {
+        super::run_signature_verification_benchmark(
+            25, /* num_accounts */
+            5,  /* block_size */
+            5,  /* num_blocks */
+        )
+        .unwrap();
+        assert!(super::run_signature_verification_benchmark(
+            1, /* num_accounts */
+            5, /* block_size */
+            5, /* num_blocks */
+        )
+        .is_err());
     }
 }
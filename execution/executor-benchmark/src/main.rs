@@ -1,11 +1,19 @@
 // Copyright (c) The Diem Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use anyhow::{bail, Result};
+use executor_benchmark::{BenchmarkOpt, BenchmarkReport, OutputFormat, ReportComparison};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
+#[cfg(feature = "alloc-stats")]
+#[global_allocator]
+static ALLOC: executor_benchmark::CountingAllocator = executor_benchmark::CountingAllocator;
+
 #[derive(Debug, StructOpt)]
 struct Opt {
+    /// Number of accounts to create and transfer between. At least two are needed for each
+    /// transfer generator.
     #[structopt(long, default_value = "1000000")]
     num_accounts: usize,
 
@@ -20,9 +28,35 @@ struct Opt {
 
     #[structopt(long, parse(from_os_str))]
     db_dir: Option<PathBuf>,
+
+    /// Only measure the signature verification throughput of `num_transfer_blocks` blocks of
+    /// transfers, without executing them.
+    #[structopt(long)]
+    signature_verification_only: bool,
+
+    /// Run this many independent workloads at once, each against its own fresh database, and
+    /// report their aggregate throughput. Can't be combined with `--db-dir`.
+    #[structopt(long, default_value = "1")]
+    num_workloads: usize,
+
+    /// Save the report of the run to this file as JSON, e.g. to compare it with `--compare` later.
+    #[structopt(long, parse(from_os_str))]
+    save_report: Option<PathBuf>,
+
+    /// Instead of running the benchmark, compare two reports saved with `--save-report`, the
+    /// baseline first, and fail if the second one regressed by more than `--tolerance-percent`.
+    #[structopt(long, number_of_values = 2, parse(from_os_str))]
+    compare: Vec<PathBuf>,
+
+    /// Largest TPS drop or latency increase, in percent, that `--compare` doesn't fail on.
+    #[structopt(long, default_value = "5")]
+    tolerance_percent: f64,
+
+    #[structopt(flatten)]
+    benchmark_opt: BenchmarkOpt,
 }
 
-fn main() {
+fn main() -> Result<()> {
     let opt = Opt::from_args();
 
     diem_logger::Logger::new().init();
@@ -32,11 +66,62 @@ fn main() {
         .build_global()
         .expect("Failed to build rayon global thread pool.");
 
-    executor_benchmark::run_benchmark(
+    if !opt.compare.is_empty() {
+        let baseline = BenchmarkReport::load(&opt.compare[0])?;
+        let current = BenchmarkReport::load(&opt.compare[1])?;
+        let comparison = ReportComparison::new(&baseline, &current, opt.tolerance_percent);
+        println!("{}", comparison);
+        return comparison.check();
+    }
+
+    if opt.signature_verification_only {
+        executor_benchmark::run_signature_verification_benchmark(
+            opt.num_accounts,
+            opt.block_size,
+            opt.num_transfer_blocks,
+        )?;
+        return Ok(());
+    }
+
+    let output_format = opt.benchmark_opt.output_format;
+    if opt.num_workloads > 1 {
+        if opt.db_dir.is_some() {
+            bail!("Concurrent workloads each need a fresh database, so db_dir can't be set.");
+        }
+        if opt.save_report.is_some() {
+            bail!("Only the report of a single workload can be saved.");
+        }
+        let reports = executor_benchmark::run_concurrent_benchmarks(
+            opt.num_workloads,
+            opt.num_accounts,
+            opt.init_account_balance,
+            opt.block_size,
+            opt.num_transfer_blocks,
+            opt.benchmark_opt,
+        )?;
+        if output_format == OutputFormat::Table {
+            for (i, report) in reports.iter().enumerate() {
+                println!("Workload {}", i);
+                println!("{}", report);
+            }
+        }
+        return Ok(());
+    }
+
+    let report = executor_benchmark::run_benchmark(
         opt.num_accounts,
         opt.init_account_balance,
         opt.block_size,
         opt.num_transfer_blocks,
         opt.db_dir,
-    );
+        opt.benchmark_opt,
+    )?;
+    if output_format == OutputFormat::Table {
+        println!("{}", report);
+    }
+    if let Some(path) = &opt.save_report {
+        report.save(path)?;
+    }
+
+    Ok(())
 }
@@ -0,0 +1,478 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{bail, format_err, Error, Result};
+use diem_crypto::HashValue;
+use diem_types::transaction::Version;
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs::File, io::BufReader, path::Path, str::FromStr, time::Duration};
+
+/// How the benchmark binary presents the report of a run once it's over.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// An aligned table with the throughput of every phase.
+    Table,
+    /// Nothing beyond the log output.
+    None,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "table" => OutputFormat::Table,
+            "none" => OutputFormat::None,
+            _ => bail!("Unknown output format {}, expected table or none.", s),
+        })
+    }
+}
+
+/// Results of a single phase of a run, i.e. of all the blocks that share a label.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PhaseReport {
+    pub label: String,
+    pub num_blocks: usize,
+    pub num_txns: usize,
+    /// Transactions that were committed to the ledger rather than discarded.
+    pub num_accepted: usize,
+    /// Serialized size of the raw transactions, without signatures.
+    pub num_bytes: usize,
+    /// Time spent executing and committing the blocks of the phase, including the commit callback.
+    pub time: Duration,
+    /// Part of `time` spent executing the blocks.
+    pub execute_time: Duration,
+    /// Part of `time` spent committing the blocks.
+    pub commit_time: Duration,
+    /// Part of `time` spent in the commit callback, if there is one.
+    pub callback_time: Duration,
+}
+
+impl PhaseReport {
+    pub fn new(label: String) -> Self {
+        Self {
+            label,
+            num_blocks: 0,
+            num_txns: 0,
+            num_accepted: 0,
+            num_bytes: 0,
+            time: Duration::from_secs(0),
+            execute_time: Duration::from_secs(0),
+            commit_time: Duration::from_secs(0),
+            callback_time: Duration::from_secs(0),
+        }
+    }
+
+    pub fn tps(&self) -> u128 {
+        tps(self.num_txns, self.time)
+    }
+
+    /// Mean time to execute a block, if any ran.
+    pub fn execute_latency(&self) -> Option<Duration> {
+        mean(self.execute_time, self.num_blocks)
+    }
+
+    /// Mean time to commit a block, if any ran.
+    pub fn commit_latency(&self) -> Option<Duration> {
+        mean(self.commit_time, self.num_blocks)
+    }
+
+    /// Megabytes of transactions per second.
+    pub fn mb_per_sec(&self) -> f64 {
+        self.num_bytes as f64 / 1_000_000.0 / self.time.as_secs_f64().max(1e-9)
+    }
+}
+
+/// Summary of a benchmark run. It can be saved as JSON, to compare runs of different versions.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BenchmarkReport {
+    pub num_accounts: usize,
+    /// Phases in the order they were first seen by the executor.
+    pub phases: Vec<PhaseReport>,
+    /// Time from starting the generator to the executor committing the last block.
+    pub wall_time: Duration,
+    /// Ledger version after the last block, counting genesis and any earlier runs.
+    pub committed_version: Version,
+    pub write_set_digest: Option<HashValue>,
+}
+
+impl BenchmarkReport {
+    /// Returns the phase with the given label, if any of its blocks ran.
+    pub fn phase(&self, label: &str) -> Option<&PhaseReport> {
+        self.phases.iter().find(|phase| phase.label == label)
+    }
+
+    /// Returns the phase with the given label, adding it if it's not there yet.
+    pub fn phase_mut(&mut self, label: &str) -> &mut PhaseReport {
+        match self.phases.iter().position(|phase| phase.label == label) {
+            Some(index) => &mut self.phases[index],
+            None => {
+                self.phases.push(PhaseReport::new(label.to_string()));
+                self.phases.last_mut().unwrap()
+            }
+        }
+    }
+
+    pub fn total_txns(&self) -> usize {
+        self.phases.iter().map(|phase| phase.num_txns).sum()
+    }
+
+    pub fn total_accepted(&self) -> usize {
+        self.phases.iter().map(|phase| phase.num_accepted).sum()
+    }
+
+    /// Writes the report to `path` as JSON.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)
+            .map_err(|e| format_err!("Failed to create report {}: {}", path.display(), e))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Reads a report written by `save`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .map_err(|e| format_err!("Failed to open report {}: {}", path.display(), e))?;
+        serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format_err!("Failed to parse report {}: {}", path.display(), e))
+    }
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Accounts: {}", self.num_accounts)?;
+        writeln!(
+            f,
+            "Transactions: {} ({} accepted)",
+            self.total_txns(),
+            self.total_accepted()
+        )?;
+        writeln!(f, "Wall time: {} ms", self.wall_time.as_millis())?;
+        writeln!(f, "Committed version: {}", self.committed_version)?;
+
+        let header = [
+            "phase",
+            "blocks",
+            "txns",
+            "accepted",
+            "time (ms)",
+            "TPS",
+            "MB/s",
+        ];
+        let rows: Vec<Vec<String>> = self
+            .phases
+            .iter()
+            .map(|phase| {
+                vec![
+                    phase.label.clone(),
+                    phase.num_blocks.to_string(),
+                    phase.num_txns.to_string(),
+                    phase.num_accepted.to_string(),
+                    phase.time.as_millis().to_string(),
+                    phase.tps().to_string(),
+                    format!("{:.2}", phase.mb_per_sec()),
+                ]
+            })
+            .collect();
+        write_table(f, &header, &rows)
+    }
+}
+
+/// A single phase in a baseline and a current run. Either may be missing if the phase only ran
+/// in one of them.
+#[derive(Clone, Debug)]
+pub struct PhaseComparison {
+    pub label: String,
+    pub baseline: Option<PhaseReport>,
+    pub current: Option<PhaseReport>,
+}
+
+impl PhaseComparison {
+    /// Relative change of the TPS from the baseline to the current run, in percent.
+    pub fn tps_delta_percent(&self) -> Option<f64> {
+        self.delta_percent(|phase| Some(phase.tps() as f64))
+    }
+
+    /// Relative change of the mean block execution latency, in percent.
+    pub fn execute_latency_delta_percent(&self) -> Option<f64> {
+        self.delta_percent(|phase| phase.execute_latency().map(|latency| latency.as_secs_f64()))
+    }
+
+    /// Relative change of the mean block commit latency, in percent.
+    pub fn commit_latency_delta_percent(&self) -> Option<f64> {
+        self.delta_percent(|phase| phase.commit_latency().map(|latency| latency.as_secs_f64()))
+    }
+
+    /// Whether the TPS dropped, or either latency rose, by more than `tolerance_percent`.
+    pub fn is_regression(&self, tolerance_percent: f64) -> bool {
+        let exceeds = |delta: Option<f64>| delta.map_or(false, |delta| delta > tolerance_percent);
+        exceeds(self.tps_delta_percent().map(|delta| -delta))
+            || exceeds(self.execute_latency_delta_percent())
+            || exceeds(self.commit_latency_delta_percent())
+    }
+
+    fn delta_percent(&self, metric: impl Fn(&PhaseReport) -> Option<f64>) -> Option<f64> {
+        let baseline = metric(self.baseline.as_ref()?)?;
+        let current = metric(self.current.as_ref()?)?;
+        if baseline > 0.0 {
+            Some((current - baseline) * 100.0 / baseline)
+        } else {
+            None
+        }
+    }
+}
+
+/// Side-by-side comparison of two benchmark runs, e.g. of two versions or two configurations.
+#[derive(Clone, Debug)]
+pub struct ReportComparison {
+    pub phases: Vec<PhaseComparison>,
+    pub baseline_wall_time: Duration,
+    pub current_wall_time: Duration,
+    /// Largest TPS drop or latency increase, in percent, that is not considered a regression.
+    pub tolerance_percent: f64,
+}
+
+impl ReportComparison {
+    /// Compares `current` against `baseline`, phase by phase. Phases are matched by label and
+    /// listed in the order of the baseline, followed by those only in the current run.
+    pub fn new(
+        baseline: &BenchmarkReport,
+        current: &BenchmarkReport,
+        tolerance_percent: f64,
+    ) -> Self {
+        let mut phases: Vec<PhaseComparison> = baseline
+            .phases
+            .iter()
+            .map(|phase| PhaseComparison {
+                label: phase.label.clone(),
+                baseline: Some(phase.clone()),
+                current: current.phase(&phase.label).cloned(),
+            })
+            .collect();
+        for phase in &current.phases {
+            if baseline.phase(&phase.label).is_none() {
+                phases.push(PhaseComparison {
+                    label: phase.label.clone(),
+                    baseline: None,
+                    current: Some(phase.clone()),
+                });
+            }
+        }
+
+        Self {
+            phases,
+            baseline_wall_time: baseline.wall_time,
+            current_wall_time: current.wall_time,
+            tolerance_percent,
+        }
+    }
+
+    /// Phases whose TPS dropped, or whose latencies rose, by more than the tolerance.
+    pub fn regressions(&self) -> Vec<&PhaseComparison> {
+        self.phases
+            .iter()
+            .filter(|phase| phase.is_regression(self.tolerance_percent))
+            .collect()
+    }
+
+    /// Returns an error naming the regressed phases, if any, so the comparison can gate CI.
+    pub fn check(&self) -> Result<()> {
+        let regressions = self.regressions();
+        if !regressions.is_empty() {
+            bail!(
+                "TPS or latency regressed by more than {}% in: {}",
+                self.tolerance_percent,
+                regressions
+                    .iter()
+                    .map(|phase| phase.label.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ReportComparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format_tps = |phase: &Option<PhaseReport>| {
+            phase
+                .as_ref()
+                .map_or_else(|| "-".to_string(), |phase| phase.tps().to_string())
+        };
+        let format_latency = |latency: Option<Duration>| {
+            latency.map_or_else(
+                || "-".to_string(),
+                |latency| format!("{:.2}", latency.as_secs_f64() * 1000.0),
+            )
+        };
+        let format_delta = |delta: Option<f64>| {
+            delta.map_or_else(|| "-".to_string(), |delta| format!("{:+.1}%", delta))
+        };
+        let header = [
+            "phase",
+            "baseline TPS",
+            "current TPS",
+            "delta",
+            "baseline execute (ms)",
+            "current execute (ms)",
+            "delta",
+            "baseline commit (ms)",
+            "current commit (ms)",
+            "delta",
+            "",
+        ];
+        let mut rows: Vec<Vec<String>> = self
+            .phases
+            .iter()
+            .map(|phase| {
+                let execute_latency = |report: &Option<PhaseReport>| {
+                    format_latency(report.as_ref().and_then(PhaseReport::execute_latency))
+                };
+                let commit_latency = |report: &Option<PhaseReport>| {
+                    format_latency(report.as_ref().and_then(PhaseReport::commit_latency))
+                };
+                vec![
+                    phase.label.clone(),
+                    format_tps(&phase.baseline),
+                    format_tps(&phase.current),
+                    format_delta(phase.tps_delta_percent()),
+                    execute_latency(&phase.baseline),
+                    execute_latency(&phase.current),
+                    format_delta(phase.execute_latency_delta_percent()),
+                    commit_latency(&phase.baseline),
+                    commit_latency(&phase.current),
+                    format_delta(phase.commit_latency_delta_percent()),
+                    if phase.is_regression(self.tolerance_percent) {
+                        "REGRESSION".to_string()
+                    } else {
+                        String::new()
+                    },
+                ]
+            })
+            .collect();
+        let mut wall_time_row = vec![
+            "wall time (ms)".to_string(),
+            self.baseline_wall_time.as_millis().to_string(),
+            self.current_wall_time.as_millis().to_string(),
+        ];
+        wall_time_row.resize(header.len(), String::new());
+        rows.push(wall_time_row);
+        write_table(f, &header, &rows)
+    }
+}
+
+/// Transactions per second, guarding against an empty time span.
+pub(crate) fn tps(num_txns: usize, time: Duration) -> u128 {
+    num_txns as u128 * 1_000_000_000 / std::cmp::max(time.as_nanos(), 1)
+}
+
+/// Mean of `total` over `count` items, if there are any.
+fn mean(total: Duration, count: usize) -> Option<Duration> {
+    if count == 0 {
+        None
+    } else {
+        Some(total / count as u32)
+    }
+}
+
+/// Writes `rows` under `header` in aligned columns: the first one left aligned, the rest right
+/// aligned.
+fn write_table(f: &mut fmt::Formatter, header: &[&str], rows: &[Vec<String>]) -> fmt::Result {
+    let mut widths: Vec<usize> = header.iter().map(|column| column.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = std::cmp::max(*width, cell.len());
+        }
+    }
+
+    let separators: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    let header: Vec<String> = header.iter().map(|column| column.to_string()).collect();
+    for row in std::iter::once(&header)
+        .chain(std::iter::once(&separators))
+        .chain(rows)
+    {
+        for (i, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            if i == 0 {
+                write!(f, "{:<width$}", cell, width = width)?;
+            } else {
+                write!(f, "  {:>width$}", cell, width = width)?;
+            }
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A report with one block per phase, from label, transactions and execute and commit time.
+    fn report(phases: &[(&str, usize, u64, u64)]) -> BenchmarkReport {
+        BenchmarkReport {
+            phases: phases
+                .iter()
+                .map(|(label, num_txns, execute_ms, commit_ms)| PhaseReport {
+                    num_blocks: 1,
+                    num_txns: *num_txns,
+                    num_accepted: *num_txns,
+                    time: Duration::from_millis(execute_ms + commit_ms),
+                    execute_time: Duration::from_millis(*execute_ms),
+                    commit_time: Duration::from_millis(*commit_ms),
+                    ..PhaseReport::new(label.to_string())
+                })
+                .collect(),
+            ..BenchmarkReport::default()
+        }
+    }
+
+    #[test]
+    fn test_report_comparison() {
+        let baseline = report(&[("mint", 1000, 500, 500), ("transfer", 1000, 500, 500)]);
+        let current = report(&[("transfer", 1000, 500, 750), ("burn", 1000, 500, 500)]);
+
+        let comparison = ReportComparison::new(&baseline, &current, 10.0);
+        let labels: Vec<_> = comparison.phases.iter().map(|p| p.label.as_str()).collect();
+        assert_eq!(labels, vec!["mint", "transfer", "burn"]);
+        assert_eq!(comparison.phases[0].tps_delta_percent(), None);
+        assert_eq!(comparison.phases[1].tps_delta_percent(), Some(-20.0));
+        assert_eq!(
+            comparison.phases[1].execute_latency_delta_percent(),
+            Some(0.0)
+        );
+        assert_eq!(
+            comparison.phases[1].commit_latency_delta_percent(),
+            Some(50.0)
+        );
+        assert_eq!(comparison.phases[2].tps_delta_percent(), None);
+        assert_eq!(comparison.regressions().len(), 1);
+        assert!(comparison.check().is_err());
+
+        // The TPS is within the tolerance, but the commit latency isn't.
+        assert!(ReportComparison::new(&baseline, &current, 25.0)
+            .check()
+            .is_err());
+        assert!(ReportComparison::new(&baseline, &current, 60.0)
+            .check()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_save_and_load_report() {
+        let path = diem_temppath::TempPath::new();
+        let saved = BenchmarkReport {
+            num_accounts: 10,
+            committed_version: 42,
+            write_set_digest: Some(HashValue::random()),
+            ..report(&[("transfer", 1000, 500, 500)])
+        };
+        saved.save(path.path()).unwrap();
+
+        let loaded = BenchmarkReport::load(path.path()).unwrap();
+        assert_eq!(loaded.num_accounts, saved.num_accounts);
+        assert_eq!(loaded.committed_version, saved.committed_version);
+        assert_eq!(loaded.write_set_digest, saved.write_set_digest);
+        assert!(ReportComparison::new(&saved, &loaded, 0.0).check().is_ok());
+    }
+}
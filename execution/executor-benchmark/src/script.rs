@@ -0,0 +1,186 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{ensure, format_err, Result};
+use diem_types::{
+    account_address::AccountAddress,
+    transaction::{parse_transaction_argument, Script, TransactionArgument},
+};
+use std::path::Path;
+use vm::file_format::{CompiledScript, SignatureToken};
+
+/// Argument of a custom script, either fixed or filled in for every transaction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScriptArgument {
+    /// Address of the account sending the transaction.
+    Sender,
+    /// Address of another, randomly picked account.
+    Receiver,
+    Value(TransactionArgument),
+}
+
+impl ScriptArgument {
+    /// Parses `{sender}`, `{receiver}` or a transaction argument such as `1u64` or `true`.
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            "{sender}" => ScriptArgument::Sender,
+            "{receiver}" => ScriptArgument::Receiver,
+            s => ScriptArgument::Value(parse_transaction_argument(s)?),
+        })
+    }
+
+    /// Type of the script parameter the argument is passed as.
+    fn signature_token(&self) -> SignatureToken {
+        match self {
+            ScriptArgument::Sender | ScriptArgument::Receiver => SignatureToken::Address,
+            ScriptArgument::Value(value) => match value {
+                TransactionArgument::U8(_) => SignatureToken::U8,
+                TransactionArgument::U64(_) => SignatureToken::U64,
+                TransactionArgument::U128(_) => SignatureToken::U128,
+                TransactionArgument::Address(_) => SignatureToken::Address,
+                TransactionArgument::U8Vector(_) => {
+                    SignatureToken::Vector(Box::new(SignatureToken::U8))
+                }
+                TransactionArgument::Bool(_) => SignatureToken::Bool,
+            },
+        }
+    }
+}
+
+/// A compiled Move script the benchmark runs in place of the transfer script, with arguments
+/// substituted per sender.
+#[derive(Clone, Debug)]
+pub struct ScriptTemplate {
+    code: Vec<u8>,
+    args: Vec<ScriptArgument>,
+}
+
+impl ScriptTemplate {
+    /// Checks that `code` deserializes to a script without type parameters whose parameters match
+    /// `args`, so a bad script fails before the run starts rather than in every transaction.
+    pub fn new(code: Vec<u8>, args: Vec<ScriptArgument>) -> Result<Self> {
+        let script = CompiledScript::deserialize(&code)
+            .map_err(|e| format_err!("Failed to load script: {:?}", e))?;
+        let script = script.as_inner();
+        ensure!(
+            script.type_parameters.is_empty(),
+            "Script takes {} type arguments, but none can be passed.",
+            script.type_parameters.len(),
+        );
+        let parameters = &script
+            .signatures
+            .get(script.parameters.0 as usize)
+            .ok_or_else(|| format_err!("Script parameters are missing."))?
+            .0;
+        // The sending account is passed as a leading `&signer` parameter, if the script takes one.
+        let parameters = match parameters.first() {
+            Some(SignatureToken::Reference(inner)) if **inner == SignatureToken::Signer => {
+                &parameters[1..]
+            }
+            _ => &parameters[..],
+        };
+        ensure!(
+            parameters.len() == args.len(),
+            "Script takes {} arguments, but {} were given.",
+            parameters.len(),
+            args.len(),
+        );
+        for (i, (parameter, arg)) in parameters.iter().zip(&args).enumerate() {
+            ensure!(
+                *parameter == arg.signature_token(),
+                "Script argument {} must be a {:?}, but {:?} was given.",
+                i,
+                parameter,
+                arg,
+            );
+        }
+        Ok(Self { code, args })
+    }
+
+    /// Reads the compiled script at `path` and parses its argument template.
+    pub fn load(path: &Path, args: &[String]) -> Result<Self> {
+        let code = std::fs::read(path)
+            .map_err(|e| format_err!("Failed to read script {}: {}", path.display(), e))?;
+        let args = args
+            .iter()
+            .map(|arg| ScriptArgument::parse(arg))
+            .collect::<Result<_>>()?;
+        Self::new(code, args)
+    }
+
+    pub fn instantiate(&self, sender: AccountAddress, receiver: AccountAddress) -> Script {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| match arg {
+                ScriptArgument::Sender => TransactionArgument::Address(sender),
+                ScriptArgument::Receiver => TransactionArgument::Address(receiver),
+                ScriptArgument::Value(value) => value.clone(),
+            })
+            .collect();
+        Script::new(self.code.clone(), vec![], args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script_argument() {
+        assert_eq!(
+            ScriptArgument::parse("{sender}").unwrap(),
+            ScriptArgument::Sender
+        );
+        assert_eq!(
+            ScriptArgument::parse(" {receiver} ").unwrap(),
+            ScriptArgument::Receiver
+        );
+        assert_eq!(
+            ScriptArgument::parse("10u64").unwrap(),
+            ScriptArgument::Value(TransactionArgument::U64(10))
+        );
+        assert!(ScriptArgument::parse("{nobody}").is_err());
+    }
+
+    #[test]
+    fn test_invalid_script() {
+        assert!(ScriptTemplate::new(vec![0, 1, 2], vec![]).is_err());
+    }
+
+    #[test]
+    fn test_script_signature() {
+        // Takes a `u64` sliding nonce and an `address`.
+        let code = transaction_builder::encode_freeze_account_script(0, AccountAddress::ZERO)
+            .code()
+            .to_vec();
+        let template = |args| ScriptTemplate::new(code.clone(), args);
+        let nonce = ScriptArgument::Value(TransactionArgument::U64(0));
+        assert!(template(vec![nonce.clone(), ScriptArgument::Receiver]).is_ok());
+        assert!(template(vec![nonce.clone(), ScriptArgument::Sender]).is_ok());
+        assert!(template(vec![nonce.clone()]).is_err());
+        assert!(template(vec![nonce.clone(), ScriptArgument::Receiver, nonce]).is_err());
+        assert!(template(vec![ScriptArgument::Receiver, ScriptArgument::Receiver]).is_err());
+
+        // Takes a currency type argument.
+        let code = transaction_builder::encode_peer_to_peer_with_metadata_script(
+            diem_types::account_config::xus_tag(),
+            AccountAddress::ZERO,
+            0,
+            vec![],
+            vec![],
+        )
+        .code()
+        .to_vec();
+        assert!(ScriptTemplate::new(
+            code,
+            vec![
+                ScriptArgument::Receiver,
+                ScriptArgument::Value(TransactionArgument::U64(1)),
+                ScriptArgument::Value(TransactionArgument::U8Vector(vec![])),
+                ScriptArgument::Value(TransactionArgument::U8Vector(vec![])),
+            ],
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use diem_config::config::RocksdbConfig;
+use diem_temppath::TempPath;
+use diem_types::{
+    account_address::AccountAddress,
+    account_config::{xus_tag, AccountResource, BalanceResource},
+    account_state::AccountState,
+};
+use diemdb::DiemDB;
+use executor_benchmark::BenchmarkReport;
+use std::{convert::TryFrom, path::Path, process::Command, str::FromStr};
+use storage_interface::DbReader;
+
+const BINARY: &str = env!("CARGO_BIN_EXE_executor-benchmark");
+
+/// Runs the benchmark binary with 25 accounts holding 10 each and 5 blocks of 5 transfers against
+/// the database in `db_dir`, and returns the report of the run.
+fn run_benchmark(db_dir: &Path, extra_args: &[&str]) -> BenchmarkReport {
+    let report_path = TempPath::new();
+    let status = Command::new(BINARY)
+        .args(&[
+            "--num-accounts",
+            "25",
+            "--init-account-balance",
+            "10",
+            "--block-size",
+            "5",
+            "--num-transfer-blocks",
+            "5",
+            "--output-format",
+            "none",
+        ])
+        .arg("--db-dir")
+        .arg(db_dir)
+        .arg("--save-report")
+        .arg(report_path.path())
+        .args(extra_args)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    BenchmarkReport::load(report_path.path()).unwrap()
+}
+
+// The storage service of a run keeps its database open until the process exits, so the replay
+// runs in a process of its own.
+#[test]
+fn test_replay() {
+    let db_dir = TempPath::new();
+    db_dir.create_as_dir().unwrap();
+    let first = run_benchmark(db_dir.path(), &[]);
+
+    let accounts_file = TempPath::new();
+    let replay_from_version = first.committed_version.to_string();
+    let replay = run_benchmark(
+        db_dir.path(),
+        &[
+            "--replay-from-version",
+            &replay_from_version,
+            "--accounts-file",
+            accounts_file.path().to_str().unwrap(),
+        ],
+    );
+    let transfer = replay.phase("transfer").unwrap();
+    assert_eq!(transfer.num_accepted, 25);
+    assert!(replay.phase("mint").is_none());
+    assert_eq!(
+        replay.committed_version,
+        first.committed_version + transfer.num_txns as u64
+    );
+
+    // Both runs' transfers are in the database, and no money was created or lost on the way.
+    let db = DiemDB::open(
+        db_dir.path(),
+        true, /* readonly */
+        None, /* pruner */
+        RocksdbConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(db.get_latest_version().unwrap(), replay.committed_version);
+    let mut total_sequence_number = 0;
+    let mut total_balance = 0;
+    let accounts = std::fs::read_to_string(accounts_file.path()).unwrap();
+    for line in accounts.lines().skip(1 /* header */) {
+        let address = AccountAddress::from_str(line.split(',').next().unwrap()).unwrap();
+        let blob = db.get_latest_account_state(address).unwrap().unwrap();
+        total_sequence_number += AccountResource::try_from(&blob).unwrap().sequence_number();
+        total_balance += AccountState::try_from(&blob)
+            .unwrap()
+            .get_resource_impl::<BalanceResource>(&BalanceResource::access_path_for(xus_tag()))
+            .unwrap()
+            .unwrap()
+            .coin();
+    }
+    assert_eq!(total_sequence_number, 50);
+    assert_eq!(total_balance, 250);
+}